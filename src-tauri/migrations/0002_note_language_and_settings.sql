@@ -0,0 +1,8 @@
+ALTER TABLE `notes` ADD COLUMN `language` text;
+--> statement-breakpoint
+ALTER TABLE `note_chunks` ADD COLUMN `language` text;
+--> statement-breakpoint
+CREATE TABLE `settings` (
+    `key` text NOT NULL PRIMARY KEY,
+    `value` text NOT NULL
+);
@@ -0,0 +1,12 @@
+CREATE TABLE `note_centroids` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `centroid` blob NOT NULL,
+    `weight` real DEFAULT 1 NOT NULL,
+    `note_id` integer NOT NULL,
+    FOREIGN KEY(`note_id`) REFERENCES notes(id) ON DELETE CASCADE
+);
+--> statement-breakpoint
+INSERT INTO note_centroids (centroid, weight, note_id)
+SELECT average_sentence_embedding, 1, id
+FROM notes
+WHERE average_sentence_embedding IS NOT NULL;
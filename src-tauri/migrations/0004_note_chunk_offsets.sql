@@ -0,0 +1,3 @@
+ALTER TABLE `note_chunks` ADD COLUMN `start_offset` integer;
+--> statement-breakpoint
+ALTER TABLE `note_chunks` ADD COLUMN `end_offset` integer;
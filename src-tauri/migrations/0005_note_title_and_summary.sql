@@ -0,0 +1,5 @@
+ALTER TABLE `notes` ADD COLUMN `title` text;
+--> statement-breakpoint
+ALTER TABLE `notes` ADD COLUMN `summary` text;
+--> statement-breakpoint
+ALTER TABLE `notes` ADD COLUMN `title_is_custom` integer DEFAULT 0 NOT NULL;
@@ -0,0 +1,15 @@
+CREATE TABLE `topics` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `label` text DEFAULT '' NOT NULL,
+    `keywords` text DEFAULT '[]' NOT NULL,
+    `centroid` blob NOT NULL,
+    `updated_at` integer DEFAULT (strftime('%s', 'now')) NOT NULL
+);
+--> statement-breakpoint
+CREATE TABLE `notes_to_topics` (
+    note_id integer NOT NULL PRIMARY KEY,
+    topic_id integer NOT NULL,
+    distance real NOT NULL,
+    FOREIGN KEY(note_id) REFERENCES notes(id) ON DELETE CASCADE,
+    FOREIGN KEY(topic_id) REFERENCES topics(id) ON DELETE CASCADE
+);
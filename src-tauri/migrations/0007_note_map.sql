@@ -0,0 +1,13 @@
+CREATE TABLE `note_map_projection` (
+    `id` integer PRIMARY KEY NOT NULL,
+    `mean` blob NOT NULL,
+    `axes` blob NOT NULL,
+    `created_at` integer DEFAULT (strftime('%s', 'now')) NOT NULL
+);
+--> statement-breakpoint
+CREATE TABLE `note_map_points` (
+    `note_id` integer PRIMARY KEY NOT NULL,
+    `x` real NOT NULL,
+    `y` real NOT NULL,
+    FOREIGN KEY(`note_id`) REFERENCES notes(id) ON DELETE CASCADE
+);
@@ -0,0 +1,30 @@
+CREATE TABLE `entities` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `name` text NOT NULL COLLATE NOCASE,
+    `label` text NOT NULL,
+    UNIQUE(`name`, `label`)
+);
+--> statement-breakpoint
+CREATE TABLE `note_entities` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `note_id` integer NOT NULL,
+    `entity_id` integer NOT NULL,
+    `start_offset` integer,
+    `end_offset` integer,
+    `score` real NOT NULL,
+    FOREIGN KEY(note_id) REFERENCES notes(id) ON DELETE CASCADE,
+    FOREIGN KEY(entity_id) REFERENCES entities(id) ON DELETE CASCADE
+);
+--> statement-breakpoint
+CREATE INDEX `note_entities_entity_id` ON `note_entities` (`entity_id`);
+--> statement-breakpoint
+CREATE INDEX `note_entities_note_id` ON `note_entities` (`note_id`);
+--> statement-breakpoint
+-- Names of merged entities, so recognizing them again keeps them merged
+CREATE TABLE `entity_aliases` (
+    `name` text NOT NULL COLLATE NOCASE,
+    `label` text NOT NULL,
+    `entity_id` integer NOT NULL,
+    PRIMARY KEY(`name`, `label`),
+    FOREIGN KEY(entity_id) REFERENCES entities(id) ON DELETE CASCADE
+);
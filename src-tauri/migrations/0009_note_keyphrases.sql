@@ -0,0 +1,8 @@
+CREATE TABLE `note_keyphrases` (
+    `note_id` integer NOT NULL,
+    `phrase` text NOT NULL,
+    `score` real NOT NULL,
+    `rank` integer NOT NULL,
+    PRIMARY KEY(`note_id`, `phrase`),
+    FOREIGN KEY(note_id) REFERENCES notes(id) ON DELETE CASCADE
+);
@@ -0,0 +1,9 @@
+CREATE TABLE `note_tag_suggestions` (
+    `note_id` integer NOT NULL,
+    `tag_id` text NOT NULL,
+    `confidence` real NOT NULL,
+    `source` text NOT NULL,
+    PRIMARY KEY(`note_id`, `tag_id`),
+    FOREIGN KEY(note_id) REFERENCES notes(id) ON DELETE CASCADE,
+    FOREIGN KEY(tag_id) REFERENCES tags(id) ON DELETE CASCADE
+);
@@ -0,0 +1,14 @@
+CREATE TABLE `note_views` (
+    `note_id` integer PRIMARY KEY NOT NULL,
+    `last_viewed_at` integer NOT NULL,
+    `view_count` integer DEFAULT 0 NOT NULL,
+    FOREIGN KEY(`note_id`) REFERENCES notes(id) ON DELETE CASCADE
+);
+--> statement-breakpoint
+CREATE TABLE `daily_resurface` (
+    `day` text NOT NULL,
+    `note_id` integer NOT NULL,
+    `rank` integer NOT NULL,
+    PRIMARY KEY(`day`, `note_id`),
+    FOREIGN KEY(`note_id`) REFERENCES notes(id) ON DELETE CASCADE
+);
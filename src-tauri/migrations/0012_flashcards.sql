@@ -0,0 +1,32 @@
+CREATE TABLE `flashcards` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `note_id` integer NOT NULL,
+    -- `basic` for Q:/A: pairs, `cloze` for {{c1::...}} deletions
+    `kind` text NOT NULL,
+    -- The question, or the whole cloze text with its markup
+    `content` text NOT NULL,
+    `answer` text DEFAULT '' NOT NULL,
+    `cloze_index` integer DEFAULT 0 NOT NULL,
+    `position` integer NOT NULL,
+    `ease_factor` real DEFAULT 2.5 NOT NULL,
+    `interval_days` integer DEFAULT 0 NOT NULL,
+    `repetitions` integer DEFAULT 0 NOT NULL,
+    `lapses` integer DEFAULT 0 NOT NULL,
+    `due_at` integer DEFAULT (strftime('%s', 'now')) NOT NULL,
+    `last_reviewed_at` integer,
+    `created_at` integer DEFAULT (strftime('%s', 'now')) NOT NULL,
+    UNIQUE(`note_id`, `kind`, `content`, `cloze_index`),
+    FOREIGN KEY(`note_id`) REFERENCES notes(id) ON DELETE CASCADE
+);
+--> statement-breakpoint
+CREATE INDEX `flashcards_due_at` ON `flashcards` (`due_at`);
+--> statement-breakpoint
+CREATE TABLE `flashcard_reviews` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `flashcard_id` integer NOT NULL,
+    `grade` integer NOT NULL,
+    `interval_days` integer NOT NULL,
+    `ease_factor` real NOT NULL,
+    `reviewed_at` integer DEFAULT (strftime('%s', 'now')) NOT NULL,
+    FOREIGN KEY(`flashcard_id`) REFERENCES flashcards(id) ON DELETE CASCADE
+);
@@ -0,0 +1,20 @@
+CREATE TABLE `note_links` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `source_note_id` integer NOT NULL,
+    -- NULL while no note matches the link
+    `target_note_id` integer,
+    -- `title` for [[Note title]], `id` for [[#id]]
+    `kind` text NOT NULL,
+    -- The title or id written in the link
+    `target_text` text NOT NULL COLLATE NOCASE,
+    `start_offset` integer NOT NULL,
+    `end_offset` integer NOT NULL,
+    FOREIGN KEY(source_note_id) REFERENCES notes(id) ON DELETE CASCADE,
+    FOREIGN KEY(target_note_id) REFERENCES notes(id) ON DELETE SET NULL
+);
+--> statement-breakpoint
+CREATE INDEX `note_links_source_note_id` ON `note_links` (`source_note_id`);
+--> statement-breakpoint
+CREATE INDEX `note_links_target_note_id` ON `note_links` (`target_note_id`);
+--> statement-breakpoint
+CREATE INDEX `note_links_target_text` ON `note_links` (`target_text`);
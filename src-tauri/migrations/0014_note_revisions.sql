@@ -0,0 +1,13 @@
+CREATE TABLE `note_revisions` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `note_id` integer NOT NULL,
+    `content` text NOT NULL,
+    `title` text,
+    -- Checkpoints are made on request and never pruned
+    `is_checkpoint` integer DEFAULT 0 NOT NULL,
+    `label` text,
+    `created_at` integer DEFAULT (strftime('%s', 'now')) NOT NULL,
+    FOREIGN KEY(`note_id`) REFERENCES notes(id) ON DELETE CASCADE
+);
+--> statement-breakpoint
+CREATE INDEX `note_revisions_note_id_created_at` ON `note_revisions` (`note_id`, `created_at`);
@@ -0,0 +1,4 @@
+-- Trashed notes are kept until they are purged
+ALTER TABLE `notes` ADD COLUMN `deleted_at` integer;
+--> statement-breakpoint
+CREATE INDEX `notes_deleted_at` ON `notes` (`deleted_at`);
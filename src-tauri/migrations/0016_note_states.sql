@@ -0,0 +1,5 @@
+ALTER TABLE `notes` ADD COLUMN `pinned` integer DEFAULT 0 NOT NULL;
+--> statement-breakpoint
+ALTER TABLE `notes` ADD COLUMN `archived` integer DEFAULT 0 NOT NULL;
+--> statement-breakpoint
+ALTER TABLE `notes` ADD COLUMN `favourite` integer DEFAULT 0 NOT NULL;
@@ -0,0 +1,18 @@
+CREATE TABLE `notebooks` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `name` text NOT NULL,
+    -- NULL for top level notebooks
+    `parent_id` integer,
+    `created_at` integer DEFAULT (strftime('%s', 'now')) NOT NULL,
+    FOREIGN KEY(`parent_id`) REFERENCES notebooks(id) ON DELETE CASCADE
+);
+--> statement-breakpoint
+-- Notes that aren't filed anywhere else live in the default notebook
+INSERT INTO `notebooks` (`id`, `name`) VALUES (1, 'Notes');
+--> statement-breakpoint
+-- No REFERENCES, columns added with a foreign key need a NULL default
+ALTER TABLE `notes` ADD COLUMN `notebook_id` integer DEFAULT 1 NOT NULL;
+--> statement-breakpoint
+CREATE INDEX `notes_notebook_id` ON `notes` (`notebook_id`);
+--> statement-breakpoint
+CREATE INDEX `notebooks_parent_id` ON `notebooks` (`parent_id`);
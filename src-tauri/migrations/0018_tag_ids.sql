@@ -0,0 +1,102 @@
+-- Tags get integer ids and a name separate from their place in the
+-- hierarchy: `work/infra/db` becomes `db` below `infra` below `work`
+ALTER TABLE `note_tag_suggestions` RENAME TO `note_tag_suggestions_old`;
+--> statement-breakpoint
+ALTER TABLE `notes_to_tags` RENAME TO `notes_to_tags_old`;
+--> statement-breakpoint
+ALTER TABLE `tags` RENAME TO `tags_old`;
+--> statement-breakpoint
+CREATE TABLE `tags` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `name` text NOT NULL COLLATE NOCASE,
+    -- NULL for top level tags
+    `parent_id` integer,
+    FOREIGN KEY(`parent_id`) REFERENCES tags(id) ON DELETE CASCADE
+);
+--> statement-breakpoint
+-- Sibling names are unique, top level tags included
+CREATE UNIQUE INDEX `tags_parent_id_name` ON `tags` (COALESCE(`parent_id`, 0), `name`);
+--> statement-breakpoint
+CREATE TABLE `notes_to_tags` (
+    `note_id` integer NOT NULL,
+    `tag_id` integer NOT NULL,
+    PRIMARY KEY(`note_id`, `tag_id`),
+    FOREIGN KEY(`tag_id`) REFERENCES tags(id) ON DELETE CASCADE
+);
+--> statement-breakpoint
+CREATE INDEX `notes_to_tags_tag_id` ON `notes_to_tags` (`tag_id`);
+--> statement-breakpoint
+CREATE TABLE `note_tag_suggestions` (
+    `note_id` integer NOT NULL,
+    `tag_id` integer NOT NULL,
+    `confidence` real NOT NULL,
+    `source` text NOT NULL,
+    PRIMARY KEY(`note_id`, `tag_id`),
+    FOREIGN KEY(`note_id`) REFERENCES notes(id) ON DELETE CASCADE,
+    FOREIGN KEY(`tag_id`) REFERENCES tags(id) ON DELETE CASCADE
+);
+--> statement-breakpoint
+-- Every prefix of every old tag, parents before their children
+CREATE TABLE `migrated_tag_paths` (
+    `id` integer PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `path` text NOT NULL UNIQUE COLLATE NOCASE,
+    `parent_path` text COLLATE NOCASE,
+    `name` text NOT NULL
+);
+--> statement-breakpoint
+-- Names now ignore case, so `Work` and `work` become one tag
+INSERT OR IGNORE INTO `migrated_tag_paths` (`path`, `parent_path`, `name`)
+WITH RECURSIVE prefixes(path, parent_path, name, rest) AS (
+    SELECT
+        substr(id, 1, instr(id || '/', '/') - 1),
+        NULL,
+        substr(id, 1, instr(id || '/', '/') - 1),
+        substr(id, instr(id || '/', '/') + 1)
+    FROM tags_old
+    UNION
+    SELECT
+        path || '/' || substr(rest, 1, instr(rest || '/', '/') - 1),
+        path,
+        substr(rest, 1, instr(rest || '/', '/') - 1),
+        substr(rest, instr(rest || '/', '/') + 1)
+    FROM prefixes
+    WHERE rest != ''
+)
+SELECT path, parent_path, name FROM prefixes
+WHERE name != ''
+ORDER BY length(path);
+--> statement-breakpoint
+INSERT INTO `tags` (`id`, `name`, `parent_id`)
+SELECT child.id, child.name, parent.id
+FROM migrated_tag_paths child
+LEFT JOIN migrated_tag_paths parent ON parent.path = child.parent_path
+ORDER BY child.id;
+--> statement-breakpoint
+INSERT OR IGNORE INTO `notes_to_tags` (`note_id`, `tag_id`)
+SELECT old.note_id, paths.id
+FROM notes_to_tags_old old
+JOIN migrated_tag_paths paths ON paths.path = old.tag_id;
+--> statement-breakpoint
+INSERT OR IGNORE INTO `note_tag_suggestions` (`note_id`, `tag_id`, `confidence`, `source`)
+SELECT old.note_id, paths.id, old.confidence, old.source
+FROM note_tag_suggestions_old old
+JOIN migrated_tag_paths paths ON paths.path = old.tag_id;
+--> statement-breakpoint
+DROP TABLE `note_tag_suggestions_old`;
+--> statement-breakpoint
+DROP TABLE `notes_to_tags_old`;
+--> statement-breakpoint
+DROP TABLE `tags_old`;
+--> statement-breakpoint
+DROP TABLE `migrated_tag_paths`;
+--> statement-breakpoint
+-- Full path of every tag, e.g. `work/infra/db`
+CREATE VIEW `tag_paths` AS
+WITH RECURSIVE paths(id, path, depth) AS (
+    SELECT id, name, 0 FROM tags WHERE parent_id IS NULL
+    UNION ALL
+    SELECT tags.id, paths.path || '/' || tags.name, paths.depth + 1
+    FROM tags
+    JOIN paths ON tags.parent_id = paths.id
+)
+SELECT id, path, depth FROM paths;
@@ -0,0 +1,35 @@
+ALTER TABLE `tags` ADD COLUMN `colour` text;
+--> statement-breakpoint
+ALTER TABLE `tags` ADD COLUMN `icon` text;
+--> statement-breakpoint
+ALTER TABLE `tags` ADD COLUMN `description` text;
+--> statement-breakpoint
+-- Added columns can't default to the current time, new tags set it themselves
+ALTER TABLE `tags` ADD COLUMN `created_at` integer DEFAULT 0 NOT NULL;
+--> statement-breakpoint
+-- Tags are given a time when they were applied, which tells when a tag was
+-- last used
+CREATE TABLE `notes_to_tags_new` (
+    `note_id` integer NOT NULL,
+    `tag_id` integer NOT NULL,
+    `created_at` integer DEFAULT (strftime('%s', 'now')) NOT NULL,
+    PRIMARY KEY(`note_id`, `tag_id`),
+    FOREIGN KEY(`tag_id`) REFERENCES tags(id) ON DELETE CASCADE
+);
+--> statement-breakpoint
+-- Existing tags count as applied when their note was last updated
+INSERT INTO `notes_to_tags_new` (`note_id`, `tag_id`, `created_at`)
+SELECT notes_to_tags.note_id, notes_to_tags.tag_id, COALESCE(notes.updated_at, strftime('%s', 'now'))
+FROM notes_to_tags
+LEFT JOIN notes ON notes.id = notes_to_tags.note_id;
+--> statement-breakpoint
+DROP TABLE `notes_to_tags`;
+--> statement-breakpoint
+ALTER TABLE `notes_to_tags_new` RENAME TO `notes_to_tags`;
+--> statement-breakpoint
+CREATE INDEX `notes_to_tags_tag_id` ON `notes_to_tags` (`tag_id`);
+--> statement-breakpoint
+UPDATE `tags` SET `created_at` = COALESCE(
+    (SELECT MIN(`created_at`) FROM `notes_to_tags` WHERE `tag_id` = `tags`.`id`),
+    strftime('%s', 'now')
+);
@@ -0,0 +1,8 @@
+-- Chunks of trashed notes leave the KNN index until the note is restored
+DELETE FROM vec_note_chunks
+WHERE rowid IN (
+    SELECT note_chunks.id
+    FROM note_chunks
+    JOIN notes ON notes.id = note_chunks.note_id
+    WHERE notes.deleted_at IS NOT NULL
+);
@@ -0,0 +1,335 @@
+use serde_json::json;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+// Fixed ids so decks exported again update the cards imported before
+const BASIC_MODEL_ID: i64 = 1718046543001;
+const CLOZE_MODEL_ID: i64 = 1718046543002;
+const DECK_ID: i64 = 1718046543100;
+// Fields of an Anki note are joined with the unit separator
+const FIELD_SEPARATOR: &str = "\x1f";
+
+// Schema of the legacy `collection.anki2` every Anki version can import
+const SCHEMA: &str = "
+CREATE TABLE col (id integer primary key, crt integer not null, mod integer not null, scm integer not null, ver integer not null, dty integer not null, usn integer not null, ls integer not null, conf text not null, models text not null, decks text not null, dconf text not null, tags text not null);
+CREATE TABLE notes (id integer primary key, guid text not null, mid integer not null, mod integer not null, usn integer not null, tags text not null, flds text not null, sfld integer not null, csum integer not null, flags integer not null, data text not null);
+CREATE TABLE cards (id integer primary key, nid integer not null, did integer not null, ord integer not null, mod integer not null, usn integer not null, type integer not null, queue integer not null, due integer not null, ivl integer not null, factor integer not null, reps integer not null, lapses integer not null, left integer not null, odue integer not null, odid integer not null, flags integer not null, data text not null);
+CREATE TABLE revlog (id integer primary key, cid integer not null, usn integer not null, ease integer not null, ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null, type integer not null);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+const CSS: &str = ".card { font-family: arial; font-size: 20px; text-align: center; color: black; background-color: white; }\n.cloze { font-weight: bold; color: blue; }";
+
+#[derive(Debug, Clone)]
+pub enum AnkiNoteFields {
+    Basic {
+        front: String,
+        back: String,
+    },
+    /// Text with `{{c1::...}}` deletions
+    Cloze {
+        text: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct AnkiNote {
+    /// Stable across exports so Anki updates the note on import
+    pub guid: String,
+    pub fields: AnkiNoteFields,
+    pub tags: Vec<String>,
+    /// Cards of the note, e.g. 0 for `c1`. Basic notes have the single card 0.
+    pub card_ords: Vec<i64>,
+}
+
+fn to_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\n', "<br>")
+}
+
+// First 8 hex digits of the SHA1 of the sort field, used by Anki to find
+// duplicates
+fn field_checksum(field: &str) -> i64 {
+    let digest = sha1_smol::Sha1::from(field).digest().to_string();
+    i64::from_str_radix(&digest[..8], 16).unwrap_or(0)
+}
+
+fn field(name: &str, ord: i64) -> serde_json::Value {
+    json!({
+        "name": name,
+        "ord": ord,
+        "sticky": false,
+        "rtl": false,
+        "font": "Arial",
+        "size": 20,
+        "media": []
+    })
+}
+
+fn template(name: &str, qfmt: &str, afmt: &str) -> serde_json::Value {
+    json!({
+        "name": name,
+        "ord": 0,
+        "qfmt": qfmt,
+        "afmt": afmt,
+        "did": null,
+        "bqfmt": "",
+        "bafmt": ""
+    })
+}
+
+fn collection_json(deck_name: &str, now: i64) -> (String, String, String, String) {
+    let models = json!({
+        BASIC_MODEL_ID.to_string(): {
+            "id": BASIC_MODEL_ID,
+            "name": "Insight Notes Basic",
+            "type": 0,
+            "mod": now,
+            "usn": -1,
+            "sortf": 0,
+            "did": DECK_ID,
+            "tmpls": [template("Card 1", "{{Front}}", "{{FrontSide}}<hr id=answer>{{Back}}")],
+            "flds": [field("Front", 0), field("Back", 1)],
+            "css": CSS,
+            "latexPre": "",
+            "latexPost": "",
+            "tags": [],
+            "vers": [],
+            "req": [[0, "all", [0]]]
+        },
+        CLOZE_MODEL_ID.to_string(): {
+            "id": CLOZE_MODEL_ID,
+            "name": "Insight Notes Cloze",
+            "type": 1,
+            "mod": now,
+            "usn": -1,
+            "sortf": 0,
+            "did": DECK_ID,
+            "tmpls": [template("Cloze", "{{cloze:Text}}", "{{cloze:Text}}<br>{{Back Extra}}")],
+            "flds": [field("Text", 0), field("Back Extra", 1)],
+            "css": CSS,
+            "latexPre": "",
+            "latexPost": "",
+            "tags": [],
+            "vers": []
+        }
+    });
+
+    let deck = |id: i64, name: &str| {
+        json!({
+            "id": id,
+            "name": name,
+            "mod": now,
+            "usn": -1,
+            "desc": "",
+            "dyn": 0,
+            "conf": 1,
+            "collapsed": false,
+            "newToday": [0, 0],
+            "revToday": [0, 0],
+            "lrnToday": [0, 0],
+            "timeToday": [0, 0],
+            "extendNew": 10,
+            "extendRev": 50
+        })
+    };
+    let decks = json!({
+        "1": deck(1, "Default"),
+        DECK_ID.to_string(): deck(DECK_ID, deck_name)
+    });
+
+    let dconf = json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "maxTaken": 60,
+            "autoplay": true,
+            "timer": 0,
+            "replayq": true,
+            "dyn": false,
+            "new": {
+                "delays": [1, 10],
+                "ints": [1, 4, 7],
+                "initialFactor": 2500,
+                "order": 1,
+                "perDay": 20,
+                "bury": true,
+                "separate": true
+            },
+            "rev": {
+                "perDay": 200,
+                "ease4": 1.3,
+                "fuzz": 0.05,
+                "ivlFct": 1,
+                "maxIvl": 36500,
+                "bury": true,
+                "minSpace": 1
+            },
+            "lapse": {
+                "delays": [10],
+                "mult": 0,
+                "minInt": 1,
+                "leechFails": 8,
+                "leechAction": 0
+            }
+        }
+    });
+
+    let conf = json!({
+        "nextPos": 1,
+        "estTimes": true,
+        "activeDecks": [1],
+        "sortType": "noteFld",
+        "timeLim": 0,
+        "sortBackwards": false,
+        "addToCur": true,
+        "curDeck": 1,
+        "newBury": true,
+        "newSpread": 0,
+        "dueCounts": true,
+        "curModel": BASIC_MODEL_ID.to_string(),
+        "collapseTime": 1200
+    });
+
+    (
+        conf.to_string(),
+        models.to_string(),
+        decks.to_string(),
+        dconf.to_string(),
+    )
+}
+
+async fn write_collection(path: &Path, deck_name: &str, notes: &[AnkiNote]) -> anyhow::Result<()> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true);
+    let db = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+
+    sqlx::query(SCHEMA).execute(&db).await?;
+
+    let now = chrono::Utc::now();
+    let now_seconds = now.timestamp();
+    let now_millis = now.timestamp_millis();
+    let (conf, models, decks, dconf) = collection_json(deck_name, now_seconds);
+
+    sqlx::query(
+        "
+        INSERT INTO col (id, crt, mod, scm, ver, dty, usn, ls, conf, models, decks, dconf, tags)
+        VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')
+        ",
+    )
+    .bind(now_seconds - now_seconds % 86400)
+    .bind(now_millis)
+    .bind(conf)
+    .bind(models)
+    .bind(decks)
+    .bind(dconf)
+    .execute(&db)
+    .await?;
+
+    // Ids are creation times in milliseconds, they only need to be unique
+    let mut next_id = now_millis;
+    let mut due = 1;
+
+    for note in notes {
+        let (model_id, fields) = match &note.fields {
+            AnkiNoteFields::Basic { front, back } => {
+                (BASIC_MODEL_ID, [to_html(front), to_html(back)])
+            }
+            AnkiNoteFields::Cloze { text } => (CLOZE_MODEL_ID, [to_html(text), String::new()]),
+        };
+        let tags = if note.tags.is_empty() {
+            String::new()
+        } else {
+            format!(" {} ", note.tags.join(" "))
+        };
+
+        let note_id = next_id;
+        next_id += 1;
+
+        sqlx::query(
+            "
+            INSERT INTO notes (id, guid, mid, mod, usn, tags, flds, sfld, csum, flags, data)
+            VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')
+            ",
+        )
+        .bind(note_id)
+        .bind(&note.guid)
+        .bind(model_id)
+        .bind(now_seconds)
+        .bind(tags)
+        .bind(fields.join(FIELD_SEPARATOR))
+        .bind(&fields[0])
+        .bind(field_checksum(&fields[0]))
+        .execute(&db)
+        .await?;
+
+        // Cards are exported as new, Anki schedules them from there
+        for ord in &note.card_ords {
+            sqlx::query(
+                "
+                INSERT INTO cards (id, nid, did, ord, mod, usn, type, queue, due, ivl, factor, reps, lapses, left, odue, odid, flags, data)
+                VALUES (?1, ?2, ?3, ?4, ?5, -1, 0, 0, ?6, 0, 0, 0, 0, 0, 0, 0, 0, '')
+                ",
+            )
+            .bind(next_id)
+            .bind(note_id)
+            .bind(DECK_ID)
+            .bind(ord)
+            .bind(now_seconds)
+            .bind(due)
+            .execute(&db)
+            .await?;
+
+            next_id += 1;
+            due += 1;
+        }
+    }
+
+    db.close().await;
+
+    Ok(())
+}
+
+/// Write notes to an Anki package (`.apkg`) holding a single deck
+pub async fn write_apkg(path: &Path, deck_name: &str, notes: &[AnkiNote]) -> anyhow::Result<()> {
+    let collection_path = std::env::temp_dir().join(format!(
+        "insight-notes-{}.anki2",
+        chrono::Utc::now().timestamp_millis()
+    ));
+
+    let written = write_collection(&collection_path, deck_name, notes).await;
+    let collection = written.and_then(|_| fs::read(&collection_path).map_err(anyhow::Error::from));
+    let _ = fs::remove_file(&collection_path);
+    let collection = collection?;
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = FileOptions::default();
+
+    zip.start_file("collection.anki2", options)?;
+    zip.write_all(&collection)?;
+    // No media files
+    zip.start_file("media", options)?;
+    zip.write_all(b"{}")?;
+    zip.finish()?;
+
+    Ok(())
+}
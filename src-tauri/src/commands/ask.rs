@@ -0,0 +1,189 @@
+use crate::commands::notes::{create_sentence_embedding, search_note_chunks, NoteChunkMatch};
+use crate::commands::settings::{
+    read_setting_or, LLM_MAX_TOKENS, LLM_MODEL_PATH, LLM_TOKENIZER_PATH,
+};
+use crate::text_generator::GenerationRequest;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::path::PathBuf;
+use tauri::Manager;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Citation {
+    /// Number the chunk was given in the prompt, e.g. 1 for [1]
+    index: usize,
+    note_id: i64,
+    note_chunk_id: i64,
+    sentence: String,
+    distance: f64,
+    /// Whether the answer refers to this chunk
+    cited: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotesAnswer {
+    answer: String,
+    citations: Vec<Citation>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnswerSpan {
+    answer: String,
+    score: f64,
+    note_id: i64,
+    note_chunk_id: i64,
+    /// Character offsets of the answer in the chunk
+    chunk_start: usize,
+    chunk_end: usize,
+    /// Character offsets of the answer in the note content, when the chunk
+    /// could be located in it
+    note_start: Option<i64>,
+    note_end: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct AskNotesToken {
+    request_id: String,
+    token: String,
+}
+
+fn build_prompt(question: &str, chunks: &[NoteChunkMatch]) -> String {
+    let context: Vec<String> = chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| format!("[{}] {}", i + 1, chunk.sentence))
+        .collect();
+
+    format!(
+        "Answer the question using only the notes below. \
+        Cite the notes you use with their number in brackets, e.g. [1]. \
+        If the notes do not contain the answer, say that you don't know.\n\n\
+        Notes:\n{}\n\nQuestion: {}\nAnswer:",
+        context.join("\n"),
+        question
+    )
+}
+
+/// Answer a question from the closest note chunks with a local model. The
+/// answer is streamed as `ask_notes_token` events tagged with `request_id`
+/// and returned in full along with the chunks it was based on.
+#[tauri::command]
+pub async fn ask_notes(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    question: String,
+    request_id: String,
+    limit: Option<i64>,
+) -> Result<NotesAnswer, String> {
+    let db = &state.db;
+
+    let question_embedding = create_sentence_embedding(&question, &state.sentence_encoder).await;
+    let chunks = search_note_chunks(db, &question_embedding, limit.unwrap_or(5)).await?;
+
+    if chunks.is_empty() {
+        return Err("No notes to answer from".into());
+    }
+
+    let llm_dir = state.models_dir.join("llm");
+    let model_path = read_setting_or(
+        db,
+        LLM_MODEL_PATH,
+        llm_dir.join("model.gguf").to_string_lossy().into_owned(),
+    )
+    .await;
+    let tokenizer_path = read_setting_or(
+        db,
+        LLM_TOKENIZER_PATH,
+        llm_dir
+            .join("tokenizer.json")
+            .to_string_lossy()
+            .into_owned(),
+    )
+    .await;
+
+    let request = GenerationRequest {
+        prompt: build_prompt(&question, &chunks),
+        model_path: PathBuf::from(model_path),
+        tokenizer_path: PathBuf::from(tokenizer_path),
+        max_tokens: read_setting_or(db, LLM_MAX_TOKENS, 256usize).await,
+    };
+
+    let (token_sender, mut token_receiver) = mpsc::unbounded_channel();
+
+    let generation = state.text_generator.generate(request, token_sender);
+    let stream_tokens = async {
+        while let Some(token) = token_receiver.recv().await {
+            let _ = app_handle.emit_all(
+                "ask_notes_token",
+                AskNotesToken {
+                    request_id: request_id.clone(),
+                    token,
+                },
+            );
+        }
+    };
+
+    let (answer, _) = futures::join!(generation, stream_tokens);
+    let answer = answer.map_err(|e| format!("Failed to generate answer {}", e))?;
+
+    let citations = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| Citation {
+            index: i + 1,
+            note_id: chunk.note_id,
+            note_chunk_id: chunk.id,
+            cited: answer.contains(&format!("[{}]", i + 1)),
+            sentence: chunk.sentence,
+            distance: chunk.distance,
+        })
+        .collect();
+
+    Ok(NotesAnswer { answer, citations })
+}
+
+/// Extract answer spans to a question from the closest note chunks, best
+/// answer first
+#[tauri::command]
+pub async fn answer_question(
+    state: tauri::State<'_, AppState>,
+    question: String,
+    limit: Option<i64>,
+) -> Result<Vec<AnswerSpan>, String> {
+    let db = &state.db;
+
+    let question_embedding = create_sentence_embedding(&question, &state.sentence_encoder).await;
+    let chunks = search_note_chunks(db, &question_embedding, limit.unwrap_or(5)).await?;
+
+    let contexts: Vec<String> = chunks.iter().map(|chunk| chunk.sentence.clone()).collect();
+    let answers = state
+        .question_answerer
+        .answer(question, contexts)
+        .await
+        .map_err(|e| format!("Failed to answer question {}", e))?;
+
+    let mut spans: Vec<AnswerSpan> = chunks
+        .iter()
+        .zip(answers)
+        .flat_map(|(chunk, chunk_answers)| {
+            chunk_answers.into_iter().map(move |answer| AnswerSpan {
+                answer: answer.answer,
+                score: answer.score,
+                note_id: chunk.note_id,
+                note_chunk_id: chunk.id,
+                chunk_start: answer.start,
+                chunk_end: answer.end,
+                note_start: chunk
+                    .start_offset
+                    .map(|offset| offset + answer.start as i64),
+                note_end: chunk.start_offset.map(|offset| offset + answer.end as i64),
+            })
+        })
+        .collect();
+
+    spans.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    Ok(spans)
+}
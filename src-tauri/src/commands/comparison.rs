@@ -0,0 +1,364 @@
+use crate::commands::keyphrases::{get_stored_keyphrases, update_note_keyphrases, Keyphrase};
+use crate::commands::notes::{
+    convert_blob_to_vec_f32, get_note_chunks_with_embeddings, NoteChunkWithEmbedding,
+};
+use crate::vector_math::cosine_similarity;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashSet;
+use std::fmt::Debug;
+
+const EXPLANATION_CHUNK_PAIRS: usize = 5;
+// Aligned chunks at least this similar say the same thing
+const SHARED_SIMILARITY: f32 = 0.95;
+// ...and at least this similar say it in other words
+const PARAPHRASED_SIMILARITY: f32 = 0.75;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChunkSide {
+    note_chunk_id: i64,
+    sentence: String,
+    start_offset: Option<i64>,
+    end_offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChunkPair {
+    a: ChunkSide,
+    b: ChunkSide,
+    /// Cosine similarity of the chunk embeddings
+    score: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkStatus {
+    Shared,
+    Paraphrased,
+    UniqueToA,
+    UniqueToB,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChunkComparison {
+    status: ChunkStatus,
+    /// None for chunks unique to B
+    a: Option<ChunkSide>,
+    /// None for chunks unique to A
+    b: Option<ChunkSide>,
+    /// Similarity of aligned chunks
+    score: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct SharedEntity {
+    id: i64,
+    name: String,
+    label: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SimilarityExplanation {
+    /// Cosine similarity of the note averages
+    similarity: f64,
+    chunk_pairs: Vec<ChunkPair>,
+    shared_keywords: Vec<String>,
+    shared_entities: Vec<SharedEntity>,
+}
+
+impl From<&NoteChunkWithEmbedding> for ChunkSide {
+    fn from(chunk: &NoteChunkWithEmbedding) -> Self {
+        ChunkSide {
+            note_chunk_id: chunk.id,
+            sentence: chunk.sentence.clone(),
+            start_offset: chunk.start_offset,
+            end_offset: chunk.end_offset,
+        }
+    }
+}
+
+/// Similarity of every chunk of `a` to every chunk of `b`, as
+/// `(index in a, index in b, similarity)`, the most similar first
+pub fn chunk_similarities(
+    a: &[NoteChunkWithEmbedding],
+    b: &[NoteChunkWithEmbedding],
+) -> Vec<(usize, usize, f32)> {
+    let mut similarities: Vec<(usize, usize, f32)> = a
+        .iter()
+        .enumerate()
+        .flat_map(|(i, chunk_a)| {
+            b.iter().enumerate().map(move |(j, chunk_b)| {
+                (
+                    i,
+                    j,
+                    cosine_similarity(&chunk_a.embedding, &chunk_b.embedding),
+                )
+            })
+        })
+        .collect();
+
+    similarities.sort_by(|x, y| y.2.total_cmp(&x.2));
+
+    similarities
+}
+
+async fn keyphrases_of(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<Vec<Keyphrase>, String> {
+    let keyphrases = get_stored_keyphrases(state, note_id).await?;
+
+    if !keyphrases.is_empty() {
+        return Ok(keyphrases);
+    }
+
+    update_note_keyphrases(state, note_id).await
+}
+
+/// Why two notes are similar: their closest chunk pairs, keyphrases of
+/// either note found in both, and entities both mention
+#[tauri::command]
+pub async fn explain_similarity(
+    state: tauri::State<'_, AppState>,
+    note_a: i64,
+    note_b: i64,
+) -> Result<SimilarityExplanation, String> {
+    let db = &state.db;
+
+    let chunks_a = get_note_chunks_with_embeddings(db, note_a).await?;
+    let chunks_b = get_note_chunks_with_embeddings(db, note_b).await?;
+
+    // Each chunk appears in one pair at most, so one chunk similar to
+    // everything doesn't hide the rest
+    let mut used_a: HashSet<usize> = HashSet::new();
+    let mut used_b: HashSet<usize> = HashSet::new();
+    let mut chunk_pairs: Vec<ChunkPair> = vec![];
+
+    for (i, j, score) in chunk_similarities(&chunks_a, &chunks_b) {
+        if chunk_pairs.len() >= EXPLANATION_CHUNK_PAIRS {
+            break;
+        }
+        if used_a.contains(&i) || used_b.contains(&j) {
+            continue;
+        }
+        used_a.insert(i);
+        used_b.insert(j);
+
+        chunk_pairs.push(ChunkPair {
+            a: (&chunks_a[i]).into(),
+            b: (&chunks_b[j]).into(),
+            score: score as f64,
+        });
+    }
+
+    let notes: Vec<(i64, String, Option<Vec<u8>>)> = sqlx::query_as(
+        "SELECT id, content, average_sentence_embedding FROM notes WHERE id IN (?1, ?2)",
+    )
+    .bind(note_a)
+    .bind(note_b)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get notes {}", e))?;
+
+    let find_note = |id: i64| notes.iter().find(|(note_id, _, _)| *note_id == id);
+    let (content_a, average_a) = match find_note(note_a) {
+        Some((_, content, average)) => (content.to_lowercase(), average.clone()),
+        None => return Err(format!("Note {} not found", note_a)),
+    };
+    let (content_b, average_b) = match find_note(note_b) {
+        Some((_, content, average)) => (content.to_lowercase(), average.clone()),
+        None => return Err(format!("Note {} not found", note_b)),
+    };
+
+    let similarity = match (average_a, average_b) {
+        (Some(average_a), Some(average_b)) => cosine_similarity(
+            &convert_blob_to_vec_f32(average_a).unwrap(),
+            &convert_blob_to_vec_f32(average_b).unwrap(),
+        ) as f64,
+        _ => 0.0,
+    };
+
+    let mut shared_keywords: Vec<String> = vec![];
+    for keyphrase in keyphrases_of(&state, note_a)
+        .await?
+        .into_iter()
+        .chain(keyphrases_of(&state, note_b).await?)
+    {
+        let phrase = keyphrase.phrase;
+        if content_a.contains(&phrase)
+            && content_b.contains(&phrase)
+            && !shared_keywords.contains(&phrase)
+        {
+            shared_keywords.push(phrase);
+        }
+    }
+
+    let shared_entities: Vec<SharedEntity> = sqlx::query_as::<_, SharedEntity>(
+        "
+        SELECT DISTINCT entities.id, entities.name, entities.label
+        FROM note_entities AS mentions_a
+        JOIN note_entities AS mentions_b ON mentions_b.entity_id = mentions_a.entity_id
+        JOIN entities ON entities.id = mentions_a.entity_id
+        WHERE mentions_a.note_id = ?1 AND mentions_b.note_id = ?2
+        ORDER BY entities.name
+        ",
+    )
+    .bind(note_a)
+    .bind(note_b)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get shared entities {}", e))?;
+
+    Ok(SimilarityExplanation {
+        similarity,
+        chunk_pairs,
+        shared_keywords,
+        shared_entities,
+    })
+}
+
+/// Align the chunks of two notes one to one, the most similar pairs first,
+/// and tell for each whether both notes share it, say it differently or only
+/// one of them has it. Chunks of A come first in their order, followed by the
+/// chunks only B has.
+fn align_chunks(
+    chunks_a: &[NoteChunkWithEmbedding],
+    chunks_b: &[NoteChunkWithEmbedding],
+) -> Vec<ChunkComparison> {
+    let mut aligned_a: Vec<Option<(usize, f32)>> = vec![None; chunks_a.len()];
+    let mut aligned_b: Vec<bool> = vec![false; chunks_b.len()];
+
+    for (i, j, score) in chunk_similarities(chunks_a, chunks_b) {
+        if score < PARAPHRASED_SIMILARITY {
+            break;
+        }
+        if aligned_a[i].is_some() || aligned_b[j] {
+            continue;
+        }
+        aligned_a[i] = Some((j, score));
+        aligned_b[j] = true;
+    }
+
+    let mut comparisons: Vec<ChunkComparison> = chunks_a
+        .iter()
+        .zip(&aligned_a)
+        .map(|(chunk_a, alignment)| match alignment {
+            Some((j, score)) => {
+                let chunk_b = &chunks_b[*j];
+                let is_shared = *score >= SHARED_SIMILARITY
+                    || chunk_a.sentence.trim() == chunk_b.sentence.trim();
+
+                ChunkComparison {
+                    status: if is_shared {
+                        ChunkStatus::Shared
+                    } else {
+                        ChunkStatus::Paraphrased
+                    },
+                    a: Some(chunk_a.into()),
+                    b: Some(chunk_b.into()),
+                    score: Some(*score as f64),
+                }
+            }
+            None => ChunkComparison {
+                status: ChunkStatus::UniqueToA,
+                a: Some(chunk_a.into()),
+                b: None,
+                score: None,
+            },
+        })
+        .collect();
+
+    comparisons.extend(
+        chunks_b
+            .iter()
+            .zip(&aligned_b)
+            .filter(|(_, aligned)| !**aligned)
+            .map(|(chunk_b, _)| ChunkComparison {
+                status: ChunkStatus::UniqueToB,
+                a: None,
+                b: Some(chunk_b.into()),
+                score: None,
+            }),
+    );
+
+    comparisons
+}
+
+/// Align the chunks of two notes by similarity, see `align_chunks`
+#[tauri::command]
+pub async fn compare_notes(
+    state: tauri::State<'_, AppState>,
+    note_a: i64,
+    note_b: i64,
+) -> Result<Vec<ChunkComparison>, String> {
+    let db = &state.db;
+
+    let chunks_a = get_note_chunks_with_embeddings(db, note_a).await?;
+    let chunks_b = get_note_chunks_with_embeddings(db, note_b).await?;
+
+    Ok(align_chunks(&chunks_a, &chunks_b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: i64, sentence: &str, embedding: Vec<f32>) -> NoteChunkWithEmbedding {
+        NoteChunkWithEmbedding {
+            id,
+            sentence: sentence.to_string(),
+            start_offset: None,
+            end_offset: None,
+            embedding,
+        }
+    }
+
+    fn summary(comparisons: &[ChunkComparison]) -> Vec<(ChunkStatus, Option<i64>, Option<i64>)> {
+        comparisons
+            .iter()
+            .map(|comparison| {
+                (
+                    comparison.status,
+                    comparison.a.as_ref().map(|side| side.note_chunk_id),
+                    comparison.b.as_ref().map(|side| side.note_chunk_id),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunks_are_aligned_one_to_one() {
+        let chunks_a = vec![
+            chunk(1, "Rust is fast", vec![1.0, 0.0, 0.0]),
+            chunk(2, "Rust is quick", vec![1.0, 0.1, 0.0]),
+            chunk(3, "Cats sleep a lot", vec![0.0, 1.0, 0.0]),
+        ];
+        let chunks_b = vec![
+            chunk(4, "Birds sing", vec![0.0, 0.0, 1.0]),
+            chunk(5, "Rust is fast", vec![1.0, 0.0, 0.0]),
+            chunk(6, "Cats nap during the day", vec![0.0, 1.0, 0.6]),
+        ];
+
+        assert_eq!(
+            summary(&align_chunks(&chunks_a, &chunks_b)),
+            vec![
+                (ChunkStatus::Shared, Some(1), Some(5)),
+                (ChunkStatus::UniqueToA, Some(2), None),
+                (ChunkStatus::Paraphrased, Some(3), Some(6)),
+                (ChunkStatus::UniqueToB, None, Some(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_text_is_shared_below_the_similarity_threshold() {
+        let chunks_a = vec![chunk(1, "Same words ", vec![1.0, 0.0])];
+        let chunks_b = vec![chunk(2, "Same words", vec![1.0, 0.5])];
+
+        assert_eq!(
+            summary(&align_chunks(&chunks_a, &chunks_b)),
+            vec![(ChunkStatus::Shared, Some(1), Some(2))]
+        );
+    }
+}
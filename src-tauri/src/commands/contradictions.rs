@@ -0,0 +1,116 @@
+use crate::commands::notes::{
+    convert_blob_to_vec_f32, get_note_chunks_with_embeddings, search_note_chunks,
+};
+use crate::vector_math::cosine_similarity;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+// Neighbours looked up per chunk of the note
+const CHUNK_NEIGHBOURS: i64 = 5;
+// Chunks less similar than this are about different things, not in conflict
+const MIN_SIMILARITY: f32 = 0.6;
+// Pairs run through the NLI model per check, the most similar first
+const MAX_PAIRS: usize = 30;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Contradiction {
+    note_chunk_id: i64,
+    sentence: String,
+    start_offset: Option<i64>,
+    other_note_id: i64,
+    other_note_chunk_id: i64,
+    other_sentence: String,
+    other_start_offset: Option<i64>,
+    /// Cosine similarity of the two chunks
+    similarity: f64,
+    /// From 0 to 1 how sure the model is that the chunks contradict
+    confidence: f64,
+}
+
+/// Chunks of other notes that are close to a chunk of this note but
+/// contradict it, according to a natural language inference model. Useful
+/// to find stale copies of documentation, e.g. two runbooks with different
+/// port numbers.
+#[tauri::command]
+pub async fn find_contradictions(
+    state: tauri::State<'_, AppState>,
+    note_id: i64,
+    threshold: Option<f64>,
+) -> Result<Vec<Contradiction>, String> {
+    let db = &state.db;
+    let threshold = threshold.unwrap_or(0.8);
+
+    let chunks = get_note_chunks_with_embeddings(db, note_id).await?;
+
+    let mut candidates: Vec<Contradiction> = vec![];
+
+    for chunk in &chunks {
+        let matches = search_note_chunks(db, &chunk.embedding, CHUNK_NEIGHBOURS).await?;
+
+        for other in matches.into_iter().filter(|other| other.note_id != note_id) {
+            let other_embedding: Option<(Vec<u8>,)> =
+                sqlx::query_as("SELECT sentence_embedding FROM note_chunks WHERE id = ?1")
+                    .bind(other.id)
+                    .fetch_optional(db)
+                    .await
+                    .map_err(|e| format!("Failed to get note chunk {}", e))?;
+            let other_embedding = match other_embedding {
+                Some((blob,)) => convert_blob_to_vec_f32(blob).unwrap(),
+                None => continue,
+            };
+
+            let similarity = cosine_similarity(&chunk.embedding, &other_embedding);
+            if similarity < MIN_SIMILARITY {
+                continue;
+            }
+
+            candidates.push(Contradiction {
+                note_chunk_id: chunk.id,
+                sentence: chunk.sentence.clone(),
+                start_offset: chunk.start_offset,
+                other_note_id: other.note_id,
+                other_note_chunk_id: other.id,
+                other_sentence: other.sentence,
+                other_start_offset: other.start_offset,
+                similarity: similarity as f64,
+                confidence: 0.0,
+            });
+        }
+    }
+
+    candidates.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+    candidates.truncate(MAX_PAIRS);
+
+    let mut contradictions: Vec<Contradiction> = vec![];
+
+    for mut candidate in candidates {
+        // Each chunk is the premise for the other as hypothesis
+        let pairs = vec![
+            (candidate.sentence.clone(), candidate.other_sentence.clone()),
+            (candidate.other_sentence.clone(), candidate.sentence.clone()),
+        ];
+
+        let inferences = state
+            .zero_shot_classifier
+            .infer(pairs)
+            .await
+            .map_err(|e| format!("Failed to check contradiction {}", e))?;
+
+        // Contradicting both ways is required, a detail only one of the
+        // chunks mentions isn't a conflict
+        let confidence = inferences
+            .iter()
+            .map(|inference| inference.contradiction)
+            .fold(1.0, f64::min);
+
+        if inferences.len() == 2 && confidence >= threshold {
+            candidate.confidence = confidence;
+            contradictions.push(candidate);
+        }
+    }
+
+    contradictions.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+    Ok(contradictions)
+}
@@ -0,0 +1,267 @@
+use crate::commands::enrichment::spawn_note_enrichment;
+use crate::commands::links::update_note_links;
+use crate::commands::notes::{
+    convert_blob_to_vec_f32, fetch_notes_by_ids, get_note_embeddings, reindex_note,
+    search_note_chunks, Note,
+};
+use crate::commands::revisions::insert_note_revision;
+use crate::commands::trash::trash_note;
+use crate::vector_math::cosine_similarity;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use tauri::Manager;
+
+// Neighbours looked up per chunk
+const CHUNK_NEIGHBOURS: i64 = 6;
+// Part of the smaller note's chunks that must match for it to be a copy
+const MIN_SHARED_CHUNKS: f64 = 0.8;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DuplicatePair {
+    note_a: i64,
+    note_b: i64,
+    /// Cosine similarity of the note averages
+    similarity: f64,
+    /// Part of the chunks of one of the notes found in the other
+    shared_chunks: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DuplicateCluster {
+    notes: Vec<Note>,
+    pairs: Vec<DuplicatePair>,
+}
+
+#[derive(Debug, FromRow)]
+struct ChunkEmbedding {
+    id: i64,
+    note_id: i64,
+    sentence_embedding: Vec<u8>,
+}
+
+fn find_root(parents: &mut HashMap<i64, i64>, id: i64) -> i64 {
+    let parent = *parents.entry(id).or_insert(id);
+    if parent == id {
+        return id;
+    }
+    let root = find_root(parents, parent);
+    parents.insert(id, root);
+    root
+}
+
+/// Groups of notes that are (near) copies of each other. Two notes are
+/// duplicates when their averages are at least `threshold` similar, or when
+/// most chunks of one have a chunk at least that similar in the other.
+#[tauri::command]
+pub async fn find_duplicates(
+    state: tauri::State<'_, AppState>,
+    threshold: Option<f64>,
+) -> Result<Vec<DuplicateCluster>, String> {
+    let db = &state.db;
+    let threshold = threshold.unwrap_or(0.95) as f32;
+
+    let chunks: Vec<ChunkEmbedding> = sqlx::query_as::<_, ChunkEmbedding>(
+        "
+        SELECT note_chunks.id, note_chunks.note_id, note_chunks.sentence_embedding
+        FROM note_chunks
+        JOIN notes ON notes.id = note_chunks.note_id
+        WHERE note_chunks.sentence_embedding IS NOT NULL AND notes.deleted_at IS NULL
+        ",
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get note chunks {}", e))?;
+
+    let embeddings: HashMap<i64, Vec<f32>> = chunks
+        .iter()
+        .map(|chunk| {
+            (
+                chunk.id,
+                convert_blob_to_vec_f32(chunk.sentence_embedding.clone()).unwrap(),
+            )
+        })
+        .collect();
+
+    let mut chunk_counts: HashMap<i64, usize> = HashMap::new();
+    // Chunks of the first note with a match in the second
+    let mut shared_counts: HashMap<(i64, i64), usize> = HashMap::new();
+
+    for chunk in &chunks {
+        *chunk_counts.entry(chunk.note_id).or_insert(0) += 1;
+
+        let embedding = &embeddings[&chunk.id];
+        let matches = search_note_chunks(db, embedding, CHUNK_NEIGHBOURS).await?;
+
+        let matched_notes: HashSet<i64> = matches
+            .iter()
+            .filter(|other| other.note_id != chunk.note_id)
+            .filter(|other| {
+                embeddings
+                    .get(&other.id)
+                    .is_some_and(|other| cosine_similarity(embedding, other) >= threshold)
+            })
+            .map(|other| other.note_id)
+            .collect();
+
+        for other_note_id in matched_notes {
+            *shared_counts
+                .entry((chunk.note_id, other_note_id))
+                .or_insert(0) += 1;
+        }
+    }
+
+    let averages: HashMap<i64, Vec<f32>> = get_note_embeddings(db).await?.into_iter().collect();
+
+    let candidates: HashSet<(i64, i64)> = shared_counts
+        .keys()
+        .map(|&(a, b)| (a.min(b), a.max(b)))
+        .collect();
+
+    let mut pairs: Vec<DuplicatePair> = vec![];
+
+    for (a, b) in candidates {
+        let share = |from: i64, to: i64| {
+            let shared = shared_counts.get(&(from, to)).copied().unwrap_or(0);
+            shared as f64 / chunk_counts.get(&from).copied().unwrap_or(1).max(1) as f64
+        };
+        let shared_chunks = share(a, b).max(share(b, a));
+
+        let similarity = match (averages.get(&a), averages.get(&b)) {
+            (Some(average_a), Some(average_b)) => cosine_similarity(average_a, average_b),
+            _ => 0.0,
+        };
+
+        if similarity >= threshold || shared_chunks >= MIN_SHARED_CHUNKS {
+            pairs.push(DuplicatePair {
+                note_a: a,
+                note_b: b,
+                similarity: similarity as f64,
+                shared_chunks,
+            });
+        }
+    }
+
+    // Connected notes form a cluster
+    let mut parents: HashMap<i64, i64> = HashMap::new();
+    for pair in &pairs {
+        let root_a = find_root(&mut parents, pair.note_a);
+        let root_b = find_root(&mut parents, pair.note_b);
+        if root_a != root_b {
+            parents.insert(root_a.max(root_b), root_a.min(root_b));
+        }
+    }
+
+    let mut clusters: HashMap<i64, (Vec<i64>, Vec<DuplicatePair>)> = HashMap::new();
+    for pair in pairs {
+        let root = find_root(&mut parents, pair.note_a);
+        let cluster = clusters.entry(root).or_default();
+        for note_id in [pair.note_a, pair.note_b] {
+            if !cluster.0.contains(&note_id) {
+                cluster.0.push(note_id);
+            }
+        }
+        cluster.1.push(pair);
+    }
+
+    let mut duplicate_clusters: Vec<DuplicateCluster> = vec![];
+    for (mut note_ids, mut pairs) in clusters.into_values() {
+        note_ids.sort();
+        pairs.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        duplicate_clusters.push(DuplicateCluster {
+            notes: fetch_notes_by_ids(db, &note_ids).await?,
+            pairs,
+        });
+    }
+
+    // Biggest clusters first
+    duplicate_clusters.sort_by(|a, b| b.notes.len().cmp(&a.notes.len()));
+
+    Ok(duplicate_clusters)
+}
+
+/// Merge notes into the oldest of them: contents are joined oldest first,
+/// tags are combined, links to the other notes now point at it and the other
+/// notes go to the trash. Returns the id of the remaining note.
+#[tauri::command]
+pub async fn merge_notes(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    note_ids: Vec<i64>,
+) -> Result<i64, String> {
+    let db = &state.db;
+
+    let notes = fetch_notes_by_ids(db, &note_ids).await?;
+    if notes.len() < 2 {
+        return Err("At least two notes are needed to merge".into());
+    }
+
+    let mut notes: Vec<(i64, String, i64)> = notes
+        .into_iter()
+        .map(|note| (note.id, note.content, note.created_at))
+        .collect();
+    notes.sort_by_key(|(id, _, created_at)| (*created_at, *id));
+
+    let (target_id, _, created_at) = notes[0].clone();
+    let merged_ids: Vec<i64> = notes[1..].iter().map(|(id, _, _)| *id).collect();
+    let content = notes
+        .iter()
+        .map(|(_, content, _)| content.trim())
+        .filter(|content| !content.is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n\n");
+
+    // The merge replaces the content of the oldest note
+    insert_note_revision(db, target_id, true, Some("Before merge")).await?;
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    for merged_id in &merged_ids {
+        sqlx::query(
+            "
+            INSERT OR IGNORE INTO notes_to_tags (note_id, tag_id, created_at)
+            SELECT ?1, tag_id, created_at FROM notes_to_tags WHERE note_id = ?2;
+            UPDATE note_links SET target_note_id = ?1 WHERE target_note_id = ?2;
+            ",
+        )
+        .bind(target_id)
+        .bind(merged_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not merge note tags and links {}", e))?;
+
+        // Trashed notes keep their tags and chunks, so they can be restored
+        trash_note(&mut tx, *merged_id).await?;
+    }
+
+    sqlx::query("UPDATE notes SET content = ?1, created_at = ?2, updated_at = ?3 WHERE id = ?4")
+        .bind(&content)
+        .bind(created_at)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(target_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not update note {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not merge notes {}", e))?;
+
+    reindex_note(&state, target_id, &content).await?;
+    update_note_links(db, target_id, &content).await?;
+
+    let _ = app_handle.emit_all("refetch_notes", "");
+    let _ = app_handle.emit_all("refetch_tags", "");
+    let _ = app_handle.emit_all("refetch_trash", "");
+    let _ = app_handle.emit_all("refetch_links", "");
+    let _ = app_handle.emit_all("refetch_note_revisions", "");
+
+    spawn_note_enrichment(app_handle, target_id);
+
+    Ok(target_id)
+}
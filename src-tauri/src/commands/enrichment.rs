@@ -0,0 +1,109 @@
+use crate::commands::entities::update_note_entities;
+use crate::commands::flashcards::update_note_flashcards;
+use crate::commands::keyphrases::update_note_keyphrases;
+use crate::commands::map::update_note_map_point;
+use crate::commands::summaries::refresh_note_title_and_summary;
+use crate::commands::tag_suggestions::update_note_tag_suggestions;
+use crate::commands::topics::update_note_topic;
+use crate::AppState;
+use std::time::Duration;
+use tauri::Manager;
+
+// Autosaves come in bursts, only the last save of a burst is enriched
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+// Supersede the enrichment runs already started for a note
+fn next_enrichment_generation(state: &AppState, note_id: i64) -> u64 {
+    let mut generations = state.enrichment_generations.lock().unwrap();
+    let generation = generations.entry(note_id).or_insert(0);
+    *generation += 1;
+    *generation
+}
+
+fn is_current_enrichment(state: &AppState, note_id: i64, generation: u64) -> bool {
+    state.enrichment_generations.lock().unwrap().get(&note_id) == Some(&generation)
+}
+
+/// Run the work that follows saving a note but shouldn't hold up the save,
+/// such as generating its title and summary, extracting its keyphrases and
+/// entities, syncing its flashcards, suggesting tags, assigning it to a topic
+/// or placing it on the map, then emit `refetch_notes`. Every call
+/// supersedes the previous ones for the note: a run superseded during the
+/// debounce doesn't start and a running one stops after its current step.
+/// Runs of all notes take turns, they share the model threads.
+pub fn spawn_note_enrichment(app_handle: tauri::AppHandle, note_id: i64) {
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        let generation = next_enrichment_generation(&state, note_id);
+        let is_current = || is_current_enrichment(&state, note_id, generation);
+
+        tokio::time::sleep(DEBOUNCE).await;
+        if !is_current() {
+            return;
+        }
+
+        let _turn = state.enrichment_lock.lock().await;
+
+        if !is_current() {
+            return;
+        }
+        if let Err(err) = refresh_note_title_and_summary(&state, note_id).await {
+            log::warn!(
+                "could not generate title and summary of note {} {}",
+                note_id,
+                err
+            );
+        }
+
+        if !is_current() {
+            return;
+        }
+        if let Err(err) = update_note_keyphrases(&state, note_id).await {
+            log::warn!("could not extract keyphrases of note {} {}", note_id, err);
+        }
+
+        if !is_current() {
+            return;
+        }
+        if let Err(err) = update_note_entities(&state, note_id).await {
+            log::warn!("could not recognize entities of note {} {}", note_id, err);
+        }
+
+        if !is_current() {
+            return;
+        }
+        match update_note_flashcards(&state, note_id).await {
+            Ok(()) => {
+                let _ = app_handle.emit_all("refetch_flashcards", "");
+            }
+            Err(err) => log::warn!("could not update flashcards of note {} {}", note_id, err),
+        }
+
+        if !is_current() {
+            return;
+        }
+        match update_note_tag_suggestions(&state, note_id, false).await {
+            Ok(true) => {
+                let _ = app_handle.emit_all("refetch_tags", "");
+            }
+            Ok(false) => {}
+            Err(err) => log::warn!("could not suggest tags for note {} {}", note_id, err),
+        }
+
+        if !is_current() {
+            return;
+        }
+        if let Err(err) = update_note_topic(&state, note_id).await {
+            log::warn!("could not update topic of note {} {}", note_id, err);
+        }
+
+        if !is_current() {
+            return;
+        }
+        if let Err(err) = update_note_map_point(&state, note_id).await {
+            log::warn!("could not place note {} on the map {}", note_id, err);
+        }
+
+        let _ = app_handle.emit_all("refetch_notes", "");
+    });
+}
@@ -0,0 +1,380 @@
+use crate::commands::notes::{fetch_notes_by_ids, Note};
+use crate::{AppState, Db};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use tauri::Manager;
+
+// People, organisations and locations. MISC is too noisy to index.
+const ENTITY_LABELS: [&str; 3] = ["PER", "ORG", "LOC"];
+const MIN_ENTITY_SCORE: f64 = 0.6;
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct Entity {
+    id: i64,
+    name: String,
+    label: String,
+    note_count: i64,
+    mention_count: i64,
+}
+
+/// Where an entity occurs in a note
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct EntityMention {
+    pub entity_id: i64,
+    pub name: String,
+    pub label: String,
+    /// Character offsets in the note content, when its chunk could be located
+    pub start_offset: Option<i64>,
+    pub end_offset: Option<i64>,
+    pub score: f64,
+}
+
+#[derive(Debug, FromRow)]
+struct ChunkRow {
+    sentence: String,
+    start_offset: Option<i64>,
+}
+
+/// Run NER over the chunks of a note and replace its entity mentions
+pub async fn update_note_entities(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let chunks: Vec<ChunkRow> = sqlx::query_as::<_, ChunkRow>(
+        "SELECT sentence, start_offset FROM note_chunks WHERE note_id = ?1 ORDER BY id",
+    )
+    .bind(note_id)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get note chunks {}", e))?;
+
+    let texts: Vec<String> = chunks.iter().map(|chunk| chunk.sentence.clone()).collect();
+    let recognized = state
+        .entity_recognizer
+        .recognize(texts)
+        .await
+        .map_err(|e| format!("Failed to recognize entities {}", e))?;
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    sqlx::query("DELETE FROM note_entities WHERE note_id = ?1")
+        .bind(note_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not delete note entities {}", e))?;
+
+    // Chunks overlap, so the same mention can be found twice
+    let mut seen: HashSet<(String, String, Option<i64>)> = HashSet::new();
+
+    for (chunk, entities) in chunks.iter().zip(recognized) {
+        for entity in entities {
+            let label = entity
+                .label
+                .trim_start_matches("B-")
+                .trim_start_matches("I-")
+                .to_string();
+            let name = entity.word.trim().to_string();
+
+            if !ENTITY_LABELS.contains(&label.as_str())
+                || entity.score < MIN_ENTITY_SCORE
+                || name.is_empty()
+            {
+                continue;
+            }
+
+            let start_offset = chunk
+                .start_offset
+                .map(|offset| offset + entity.offset.begin as i64);
+            let end_offset = chunk
+                .start_offset
+                .map(|offset| offset + entity.offset.end as i64);
+
+            if !seen.insert((name.to_lowercase(), label.clone(), start_offset)) {
+                continue;
+            }
+
+            let alias: Option<(i64,)> = sqlx::query_as(
+                "SELECT entity_id FROM entity_aliases WHERE name = ?1 AND label = ?2",
+            )
+            .bind(&name)
+            .bind(&label)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to get entity alias {}", e))?;
+
+            let entity_id = match alias {
+                Some((entity_id,)) => entity_id,
+                None => {
+                    sqlx::query("INSERT OR IGNORE INTO entities (name, label) VALUES (?1, ?2)")
+                        .bind(&name)
+                        .bind(&label)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|e| format!("could not insert entity {}", e))?;
+
+                    let (entity_id,): (i64,) =
+                        sqlx::query_as("SELECT id FROM entities WHERE name = ?1 AND label = ?2")
+                            .bind(&name)
+                            .bind(&label)
+                            .fetch_one(&mut *tx)
+                            .await
+                            .map_err(|e| format!("Failed to get entity {}", e))?;
+                    entity_id
+                }
+            };
+
+            sqlx::query(
+                "
+                INSERT INTO note_entities (note_id, entity_id, start_offset, end_offset, score)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                ",
+            )
+            .bind(note_id)
+            .bind(entity_id)
+            .bind(start_offset)
+            .bind(end_offset)
+            .bind(entity.score)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("could not insert note entity {}", e))?;
+        }
+    }
+
+    // Entities no note mentions anymore
+    sqlx::query("DELETE FROM entities WHERE id NOT IN (SELECT entity_id FROM note_entities)")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not delete entities {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not save note entities {}", e))?;
+
+    Ok(())
+}
+
+/// Entities of a note in the order they occur
+pub async fn get_note_entity_mentions(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<Vec<EntityMention>, String> {
+    sqlx::query_as::<_, EntityMention>(
+        "
+        SELECT
+            note_entities.entity_id,
+            entities.name,
+            entities.label,
+            note_entities.start_offset,
+            note_entities.end_offset,
+            note_entities.score
+        FROM note_entities
+        JOIN entities ON entities.id = note_entities.entity_id
+        WHERE note_entities.note_id = ?1
+        ORDER BY note_entities.start_offset
+        ",
+    )
+    .bind(note_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| format!("Failed to get note entities {}", e))
+}
+
+async fn fetch_entities(db: &Db, label: Option<String>) -> Result<Vec<Entity>, String> {
+    sqlx::query_as::<_, Entity>(
+        "
+        SELECT
+            entities.id,
+            entities.name,
+            entities.label,
+            COUNT(DISTINCT note_entities.note_id) AS note_count,
+            COUNT(note_entities.id) AS mention_count
+        FROM entities
+        JOIN note_entities ON note_entities.entity_id = entities.id
+        JOIN notes ON notes.id = note_entities.note_id
+        WHERE (?1 IS NULL OR entities.label = ?1) AND notes.deleted_at IS NULL
+        GROUP BY entities.id
+        ORDER BY note_count DESC, entities.name
+        ",
+    )
+    .bind(label)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get entities {}", e))
+}
+
+/// Entities found in the notes, the most mentioned first. `label` is one of
+/// PER, ORG or LOC.
+#[tauri::command]
+pub async fn get_entities(
+    state: tauri::State<'_, AppState>,
+    label: Option<String>,
+) -> Result<Vec<Entity>, String> {
+    fetch_entities(&state.db, label).await
+}
+
+#[tauri::command]
+pub async fn get_note_entities(
+    state: tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<Vec<EntityMention>, String> {
+    get_note_entity_mentions(&state, note_id).await
+}
+
+/// Notes mentioning an entity, the ones mentioning it most first
+#[tauri::command]
+pub async fn get_notes_mentioning_entity(
+    state: tauri::State<'_, AppState>,
+    entity_id: i64,
+) -> Result<Vec<Note>, String> {
+    let db = &state.db;
+
+    let note_ids: Vec<(i64,)> = sqlx::query_as(
+        "
+        SELECT note_id
+        FROM note_entities
+        WHERE entity_id = ?1
+        GROUP BY note_id
+        ORDER BY COUNT(*) DESC
+        ",
+    )
+    .bind(entity_id)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get entity notes {}", e))?;
+
+    let note_ids: Vec<i64> = note_ids.into_iter().map(|(id,)| id).collect();
+
+    fetch_notes_by_ids(db, &note_ids).await
+}
+
+/// Fold duplicate entities into `entity_id`. Their names are kept as aliases
+/// so they stay merged when notes are recognized again.
+#[tauri::command]
+pub async fn merge_entities(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    entity_id: i64,
+    duplicate_ids: Vec<i64>,
+) -> Result<(), String> {
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    for duplicate_id in duplicate_ids.into_iter().filter(|id| *id != entity_id) {
+        sqlx::query(
+            "
+            INSERT OR REPLACE INTO entity_aliases (name, label, entity_id)
+            SELECT name, label, ?1 FROM entities WHERE id = ?2
+            ",
+        )
+        .bind(entity_id)
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not create entity alias {}", e))?;
+
+        sqlx::query(
+            "
+            UPDATE entity_aliases SET entity_id = ?1 WHERE entity_id = ?2;
+            UPDATE note_entities SET entity_id = ?1 WHERE entity_id = ?2;
+            DELETE FROM entities WHERE id = ?2;
+            ",
+        )
+        .bind(entity_id)
+        .bind(duplicate_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not merge entity {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not merge entities {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_entities", "");
+
+    Ok(())
+}
+
+/// Recognize the entities of every note, e.g. for notes saved before
+/// entities were indexed
+#[tauri::command]
+pub async fn reindex_entities(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let note_ids: Vec<(i64,)> = sqlx::query_as("SELECT id FROM notes")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| format!("Failed to get notes {}", e))?;
+
+    for (note_id,) in note_ids {
+        update_note_entities(&state, note_id).await?;
+    }
+
+    let _ = app_handle.emit_all("refetch_entities", "");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup_test_db;
+
+    #[tokio::test]
+    async fn trashed_notes_are_not_counted() {
+        let db = setup_test_db().await;
+
+        let entity_id: i64 = sqlx::query_scalar(
+            "INSERT INTO entities (name, label) VALUES ('Ada Lovelace', 'PER') RETURNING id",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+        let mut note_ids = vec![];
+        for _ in 0..2 {
+            let note_id: i64 =
+                sqlx::query_scalar("INSERT INTO notes (content) VALUES ('') RETURNING id")
+                    .fetch_one(&db)
+                    .await
+                    .unwrap();
+            sqlx::query("INSERT INTO note_entities (note_id, entity_id, score) VALUES (?1, ?2, 1)")
+                .bind(note_id)
+                .bind(entity_id)
+                .execute(&db)
+                .await
+                .unwrap();
+            note_ids.push(note_id);
+        }
+
+        sqlx::query("UPDATE notes SET deleted_at = 1 WHERE id = ?1")
+            .bind(note_ids[1])
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let entities = fetch_entities(&db, None).await.unwrap();
+        assert_eq!(entities.len(), 1);
+        assert_eq!(entities[0].note_count, 1);
+        assert_eq!(entities[0].mention_count, 1);
+
+        sqlx::query("UPDATE notes SET deleted_at = 1 WHERE id = ?1")
+            .bind(note_ids[0])
+            .execute(&db)
+            .await
+            .unwrap();
+
+        assert!(fetch_entities(&db, None).await.unwrap().is_empty());
+    }
+}
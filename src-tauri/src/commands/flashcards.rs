@@ -0,0 +1,709 @@
+use crate::anki::{write_apkg, AnkiNote, AnkiNoteFields};
+use crate::AppState;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::path::PathBuf;
+use tauri::Manager;
+
+const DAY: i64 = 24 * 60 * 60;
+const MIN_EASE_FACTOR: f64 = 1.3;
+// {{c1::answer}} or {{c1::answer::hint}}
+const CLOZE_PATTERN: &str = r"\{\{c(\d+)::(.*?)(?:::([^}]*))?\}\}";
+
+#[derive(Debug)]
+struct ParsedFlashcard {
+    kind: &'static str,
+    content: String,
+    answer: String,
+    cloze_index: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct FlashcardRow {
+    id: i64,
+    note_id: i64,
+    kind: String,
+    content: String,
+    answer: String,
+    cloze_index: i64,
+    ease_factor: f64,
+    interval_days: i64,
+    repetitions: i64,
+    lapses: i64,
+    due_at: i64,
+    last_reviewed_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Flashcard {
+    id: i64,
+    note_id: i64,
+    /// `basic` or `cloze`
+    kind: String,
+    question: String,
+    answer: String,
+    ease_factor: f64,
+    interval_days: i64,
+    repetitions: i64,
+    lapses: i64,
+    due_at: i64,
+    last_reviewed_at: Option<i64>,
+}
+
+/// Review state of a card that SM-2 updates
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Schedule {
+    ease_factor: f64,
+    interval_days: i64,
+    repetitions: i64,
+    lapses: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FlashcardExportParams {
+    path: String,
+    deck_name: Option<String>,
+    /// Only export the cards of these notes
+    note_ids: Option<Vec<i64>>,
+}
+
+/// Question shows the deletion being asked as `[...]` (or its hint), the
+/// answer shows every deletion
+fn render_cloze(text: &str, cloze_index: i64) -> (String, String) {
+    let pattern = Regex::new(CLOZE_PATTERN).unwrap();
+
+    let question = pattern.replace_all(text, |captures: &regex::Captures| {
+        let index: i64 = captures[1].parse().unwrap_or(0);
+        if index == cloze_index {
+            format!("[{}]", captures.get(3).map_or("...", |hint| hint.as_str()))
+        } else {
+            captures[2].to_string()
+        }
+    });
+    let answer = pattern.replace_all(text, "$2");
+
+    (question.into_owned(), answer.into_owned())
+}
+
+impl From<FlashcardRow> for Flashcard {
+    fn from(row: FlashcardRow) -> Self {
+        let (question, answer) = if row.kind == "cloze" {
+            render_cloze(&row.content, row.cloze_index)
+        } else {
+            (row.content, row.answer)
+        };
+
+        Flashcard {
+            id: row.id,
+            note_id: row.note_id,
+            kind: row.kind,
+            question,
+            answer,
+            ease_factor: row.ease_factor,
+            interval_days: row.interval_days,
+            repetitions: row.repetitions,
+            lapses: row.lapses,
+            due_at: row.due_at,
+            last_reviewed_at: row.last_reviewed_at,
+        }
+    }
+}
+
+/// Schedule after a review graded from 0 to 5 with SM-2. Grades below 3
+/// forget the card and leave its ease factor as it was.
+fn next_schedule(schedule: Schedule, grade: u8) -> Schedule {
+    if grade >= 3 {
+        let quality = grade as f64;
+        let ease_factor = (schedule.ease_factor
+            + (0.1 - (5.0 - quality) * (0.08 + (5.0 - quality) * 0.02)))
+            .max(MIN_EASE_FACTOR);
+        let interval_days = match schedule.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (schedule.interval_days as f64 * schedule.ease_factor).round() as i64,
+        };
+        Schedule {
+            ease_factor,
+            interval_days,
+            repetitions: schedule.repetitions + 1,
+            lapses: schedule.lapses,
+        }
+    } else {
+        // Forgotten cards start over
+        Schedule {
+            ease_factor: schedule.ease_factor,
+            interval_days: 1,
+            repetitions: 0,
+            lapses: schedule.lapses + 1,
+        }
+    }
+}
+
+/// `Q:` lines followed by `A:` lines. Either can continue on the next lines,
+/// a blank line ends the pair.
+fn parse_question_answers(content: &str) -> Vec<ParsedFlashcard> {
+    let mut flashcards: Vec<ParsedFlashcard> = vec![];
+    let mut question: Option<String> = None;
+    let mut answer: Option<String> = None;
+
+    let mut flush = |question: &mut Option<String>, answer: &mut Option<String>| {
+        if let (Some(q), Some(a)) = (question.take(), answer.take()) {
+            if !q.is_empty() && !a.is_empty() {
+                flashcards.push(ParsedFlashcard {
+                    kind: "basic",
+                    content: q,
+                    answer: a,
+                    cloze_index: 0,
+                });
+            }
+        }
+    };
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if let Some(rest) = line.strip_prefix("Q:") {
+            flush(&mut question, &mut answer);
+            question = Some(rest.trim().to_string());
+        } else if let Some(rest) = line.strip_prefix("A:") {
+            if question.is_some() {
+                answer = Some(rest.trim().to_string());
+            }
+        } else if line.is_empty() {
+            flush(&mut question, &mut answer);
+        } else if let Some(answer) = answer.as_mut() {
+            answer.push('\n');
+            answer.push_str(line);
+        } else if let Some(question) = question.as_mut() {
+            question.push('\n');
+            question.push_str(line);
+        }
+    }
+    flush(&mut question, &mut answer);
+
+    flashcards
+}
+
+/// One card per deletion number of every paragraph with `{{cN::...}}`.
+/// Numbers start at 1 like in Anki, `{{c0::...}}` is no card.
+fn parse_clozes(content: &str) -> Vec<ParsedFlashcard> {
+    let pattern = Regex::new(CLOZE_PATTERN).unwrap();
+
+    content
+        .split("\n\n")
+        .map(str::trim)
+        .flat_map(|paragraph| {
+            let indexes: BTreeSet<i64> = pattern
+                .captures_iter(paragraph)
+                .filter_map(|captures| captures[1].parse().ok())
+                .filter(|cloze_index| *cloze_index > 0)
+                .collect();
+
+            indexes.into_iter().map(move |cloze_index| ParsedFlashcard {
+                kind: "cloze",
+                content: paragraph.to_string(),
+                answer: String::new(),
+                cloze_index,
+            })
+        })
+        .collect()
+}
+
+fn parse_flashcards(content: &str) -> Vec<ParsedFlashcard> {
+    let mut flashcards = parse_question_answers(content);
+    flashcards.extend(parse_clozes(content));
+    flashcards
+}
+
+/// Sync the flashcards of a note with its content. Cards that are still in
+/// the note keep their review schedule.
+pub async fn update_note_flashcards(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let (content,): (String,) = sqlx::query_as("SELECT content FROM notes WHERE id = ?1")
+        .bind(note_id)
+        .fetch_one(db)
+        .await
+        .map_err(|e| format!("Failed to get note {}", e))?;
+
+    let flashcards = parse_flashcards(&content);
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    let mut kept_ids: Vec<i64> = vec![];
+
+    for (position, flashcard) in flashcards.iter().enumerate() {
+        let (id,): (i64,) = sqlx::query_as(
+            "
+            INSERT INTO flashcards (note_id, kind, content, answer, cloze_index, position)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(note_id, kind, content, cloze_index) DO UPDATE SET
+                answer = excluded.answer,
+                position = excluded.position
+            RETURNING id
+            ",
+        )
+        .bind(note_id)
+        .bind(flashcard.kind)
+        .bind(&flashcard.content)
+        .bind(&flashcard.answer)
+        .bind(flashcard.cloze_index)
+        .bind(position as i64)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| format!("could not save flashcard {}", e))?;
+
+        kept_ids.push(id);
+    }
+
+    let existing_ids: Vec<(i64,)> = sqlx::query_as("SELECT id FROM flashcards WHERE note_id = ?1")
+        .bind(note_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to get flashcards {}", e))?;
+
+    for (id,) in existing_ids {
+        if kept_ids.contains(&id) {
+            continue;
+        }
+        sqlx::query("DELETE FROM flashcards WHERE id = ?1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("could not delete flashcard {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not save flashcards {}", e))?;
+
+    Ok(())
+}
+
+const FLASHCARD_COLUMNS: &str = "id, note_id, kind, content, answer, cloze_index, ease_factor, interval_days, repetitions, lapses, due_at, last_reviewed_at";
+
+#[tauri::command]
+pub async fn get_note_flashcards(
+    state: tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<Vec<Flashcard>, String> {
+    let rows: Vec<FlashcardRow> = sqlx::query_as::<_, FlashcardRow>(&format!(
+        "SELECT {} FROM flashcards WHERE note_id = ?1 ORDER BY position",
+        FLASHCARD_COLUMNS
+    ))
+    .bind(note_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| format!("Failed to get flashcards {}", e))?;
+
+    Ok(rows.into_iter().map(Flashcard::from).collect())
+}
+
+/// Cards due for review, the most overdue first
+#[tauri::command]
+pub async fn get_due_flashcards(
+    state: tauri::State<'_, AppState>,
+    note_id: Option<i64>,
+    take: Option<i64>,
+) -> Result<Vec<Flashcard>, String> {
+    let rows: Vec<FlashcardRow> = sqlx::query_as::<_, FlashcardRow>(&format!(
+        "
+        SELECT {}
+        FROM flashcards
+        WHERE due_at <= ?1
+            AND (?2 IS NULL OR note_id = ?2)
+            AND note_id IN (SELECT id FROM notes WHERE deleted_at IS NULL)
+        ORDER BY due_at
+        LIMIT ?3
+        ",
+        FLASHCARD_COLUMNS
+    ))
+    .bind(chrono::Utc::now().timestamp())
+    .bind(note_id)
+    .bind(take.unwrap_or(20))
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| format!("Failed to get due flashcards {}", e))?;
+
+    Ok(rows.into_iter().map(Flashcard::from).collect())
+}
+
+/// Grade a review from 0 (blackout) to 5 (perfect recall) and schedule the
+/// next one with SM-2
+#[tauri::command]
+pub async fn review_flashcard(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    grade: u8,
+) -> Result<Flashcard, String> {
+    let db = &state.db;
+
+    if grade > 5 {
+        return Err(format!("Grade must be between 0 and 5, got {}", grade));
+    }
+
+    let row: FlashcardRow = sqlx::query_as::<_, FlashcardRow>(&format!(
+        "SELECT {} FROM flashcards WHERE id = ?1",
+        FLASHCARD_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(db)
+    .await
+    .map_err(|e| format!("Failed to get flashcard {}", e))?;
+
+    let Schedule {
+        ease_factor,
+        interval_days,
+        repetitions,
+        lapses,
+    } = next_schedule(
+        Schedule {
+            ease_factor: row.ease_factor,
+            interval_days: row.interval_days,
+            repetitions: row.repetitions,
+            lapses: row.lapses,
+        },
+        grade,
+    );
+
+    let now = chrono::Utc::now().timestamp();
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    sqlx::query(
+        "
+        UPDATE flashcards
+        SET ease_factor = ?1, interval_days = ?2, repetitions = ?3, lapses = ?4, due_at = ?5, last_reviewed_at = ?6
+        WHERE id = ?7
+        ",
+    )
+    .bind(ease_factor)
+    .bind(interval_days)
+    .bind(repetitions)
+    .bind(lapses)
+    .bind(now + interval_days * DAY)
+    .bind(now)
+    .bind(id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("could not update flashcard {}", e))?;
+
+    sqlx::query(
+        "
+        INSERT INTO flashcard_reviews (flashcard_id, grade, interval_days, ease_factor, reviewed_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ",
+    )
+    .bind(id)
+    .bind(grade as i64)
+    .bind(interval_days)
+    .bind(ease_factor)
+    .bind(now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("could not save review {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not save review {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_flashcards", "");
+
+    Ok(Flashcard::from(FlashcardRow {
+        ease_factor,
+        interval_days,
+        repetitions,
+        lapses,
+        due_at: now + interval_days * DAY,
+        last_reviewed_at: Some(now),
+        ..row
+    }))
+}
+
+/// Parse the flashcards of every note again
+#[tauri::command]
+pub async fn reindex_flashcards(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let note_ids: Vec<(i64,)> = sqlx::query_as("SELECT id FROM notes")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| format!("Failed to get notes {}", e))?;
+
+    for (note_id,) in note_ids {
+        update_note_flashcards(&state, note_id).await?;
+    }
+
+    let _ = app_handle.emit_all("refetch_flashcards", "");
+
+    Ok(())
+}
+
+/// Export flashcards to an Anki package. Cloze cards of the same paragraph
+/// become one Anki note and note tags carry over. Returns the number of
+/// exported cards.
+#[tauri::command]
+pub async fn export_flashcards_to_anki(
+    state: tauri::State<'_, AppState>,
+    params: FlashcardExportParams,
+) -> Result<usize, String> {
+    let db = &state.db;
+
+    let note_condition = match &params.note_ids {
+        Some(note_ids) => {
+            let placeholders: Vec<String> = note_ids
+                .iter()
+                .enumerate()
+                .map(|(i, _)| format!("?{}", i + 1))
+                .collect();
+            format!("AND note_id IN ({})", placeholders.join(", "))
+        }
+        None => String::new(),
+    };
+
+    // Cloze cards numbered 0 have no Anki card, they predate parse_clozes
+    // skipping them
+    let query_str = format!(
+        "
+        SELECT {} FROM flashcards
+        WHERE note_id IN (SELECT id FROM notes WHERE deleted_at IS NULL)
+            AND (kind != 'cloze' OR cloze_index > 0)
+            {}
+        ORDER BY note_id, position
+        ",
+        FLASHCARD_COLUMNS, note_condition
+    );
+
+    let mut query = sqlx::query_as::<_, FlashcardRow>(&query_str);
+    for note_id in params.note_ids.iter().flatten() {
+        query = query.bind(note_id);
+    }
+
+    let rows: Vec<FlashcardRow> = query
+        .fetch_all(db)
+        .await
+        .map_err(|e| format!("Failed to get flashcards {}", e))?;
+    let card_count = rows.len();
+
+    let tag_rows: Vec<(i64, String)> = sqlx::query_as(
+        "
+        SELECT notes_to_tags.note_id, tag_paths.path
+        FROM notes_to_tags
+        JOIN tag_paths ON tag_paths.id = notes_to_tags.tag_id
+        ",
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get note tags {}", e))?;
+    let mut tags_by_note: HashMap<i64, Vec<String>> = HashMap::new();
+    for (note_id, tag_path) in tag_rows {
+        // Anki tags can't contain spaces and nest with `::`
+        tags_by_note
+            .entry(note_id)
+            .or_default()
+            .push(tag_path.replace(' ', "_").replace('/', "::"));
+    }
+
+    let mut anki_notes: Vec<AnkiNote> = vec![];
+    // Index of the Anki note of each cloze paragraph
+    let mut cloze_notes: HashMap<(i64, String), usize> = HashMap::new();
+
+    for row in rows {
+        let tags = tags_by_note.get(&row.note_id).cloned().unwrap_or_default();
+
+        if row.kind == "cloze" {
+            let key = (row.note_id, row.content.clone());
+            match cloze_notes.get(&key) {
+                Some(&index) => anki_notes[index].card_ords.push(row.cloze_index - 1),
+                None => {
+                    cloze_notes.insert(key, anki_notes.len());
+                    anki_notes.push(AnkiNote {
+                        guid: format!("insight-notes-{}", row.id),
+                        fields: AnkiNoteFields::Cloze { text: row.content },
+                        tags,
+                        card_ords: vec![row.cloze_index - 1],
+                    });
+                }
+            }
+        } else {
+            anki_notes.push(AnkiNote {
+                guid: format!("insight-notes-{}", row.id),
+                fields: AnkiNoteFields::Basic {
+                    front: row.content,
+                    back: row.answer,
+                },
+                tags,
+                card_ords: vec![0],
+            });
+        }
+    }
+
+    let deck_name = params
+        .deck_name
+        .unwrap_or_else(|| "Insight Notes".to_string());
+
+    write_apkg(&PathBuf::from(&params.path), &deck_name, &anki_notes)
+        .await
+        .map_err(|e| format!("could not export flashcards {}", e))?;
+
+    Ok(card_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(ease_factor: f64, interval_days: i64, repetitions: i64, lapses: i64) -> Schedule {
+        Schedule {
+            ease_factor,
+            interval_days,
+            repetitions,
+            lapses,
+        }
+    }
+
+    #[test]
+    fn parses_question_answer_pairs() {
+        let cards = parse_question_answers("Q: What is Rust?\nA: A language\n\nQ: Why?\nA: Speed");
+
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].kind, "basic");
+        assert_eq!(cards[0].content, "What is Rust?");
+        assert_eq!(cards[0].answer, "A language");
+        assert_eq!(cards[1].content, "Why?");
+        assert_eq!(cards[1].answer, "Speed");
+    }
+
+    #[test]
+    fn continues_questions_and_answers_on_the_next_lines() {
+        let cards = parse_question_answers("Q: First line\nsecond line\nA: One\n  two\n\nthree");
+
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].content, "First line\nsecond line");
+        assert_eq!(cards[0].answer, "One\ntwo");
+    }
+
+    #[test]
+    fn a_new_question_ends_the_previous_pair() {
+        let cards = parse_question_answers("Q: One\nA: 1\nQ: Two\nA: 2");
+
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].answer, "1");
+        assert_eq!(cards[1].content, "Two");
+    }
+
+    #[test]
+    fn skips_questions_without_answers() {
+        assert!(parse_question_answers("Q: Unanswered\n\nA: Orphan answer").is_empty());
+        assert!(parse_question_answers("Q:\nA: No question").is_empty());
+        assert!(parse_question_answers("Q: No answer\nA:").is_empty());
+    }
+
+    #[test]
+    fn parses_one_card_per_cloze_number_and_paragraph() {
+        let content = "{{c1::Paris}} is the capital of {{c2::France}}, {{c1::Paris}} again\n\n\
+                       {{c1::Rome}} is in Italy";
+        let cards = parse_clozes(content);
+
+        let indexes: Vec<(i64, &str)> = cards
+            .iter()
+            .map(|card| (card.cloze_index, card.content.as_str()))
+            .collect();
+        assert_eq!(
+            indexes,
+            vec![
+                (
+                    1,
+                    "{{c1::Paris}} is the capital of {{c2::France}}, {{c1::Paris}} again"
+                ),
+                (
+                    2,
+                    "{{c1::Paris}} is the capital of {{c2::France}}, {{c1::Paris}} again"
+                ),
+                (1, "{{c1::Rome}} is in Italy"),
+            ]
+        );
+        assert!(cards.iter().all(|card| card.kind == "cloze"));
+    }
+
+    #[test]
+    fn rejects_cloze_number_zero() {
+        let cards = parse_clozes("{{c0::Zero}} and {{c1::one}}");
+
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].cloze_index, 1);
+        assert!(parse_clozes("Only {{c0::zero}}").is_empty());
+    }
+
+    #[test]
+    fn renders_the_asked_deletion_with_its_hint() {
+        let text = "{{c1::Paris::city}} is in {{c2::France}}";
+
+        assert_eq!(
+            render_cloze(text, 1),
+            (
+                "[city] is in France".to_string(),
+                "Paris is in France".to_string()
+            )
+        );
+        assert_eq!(
+            render_cloze(text, 2),
+            (
+                "Paris is in [...]".to_string(),
+                "Paris is in France".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn schedules_passed_reviews_further_out() {
+        let first = next_schedule(schedule(2.5, 0, 0, 0), 4);
+        assert_eq!(first, schedule(2.5, 1, 1, 0));
+
+        let second = next_schedule(first, 4);
+        assert_eq!(second, schedule(2.5, 6, 2, 0));
+
+        let third = next_schedule(second, 4);
+        assert_eq!(third, schedule(2.5, 15, 3, 0));
+    }
+
+    #[test]
+    fn adjusts_ease_with_the_grade() {
+        assert!((next_schedule(schedule(2.5, 0, 0, 0), 5).ease_factor - 2.6).abs() < 1e-9);
+        assert!((next_schedule(schedule(2.5, 0, 0, 0), 3).ease_factor - 2.36).abs() < 1e-9);
+    }
+
+    #[test]
+    fn forgotten_cards_start_over() {
+        let forgotten = next_schedule(schedule(2.5, 15, 3, 1), 2);
+
+        assert_eq!(forgotten.ease_factor, 2.5);
+        assert_eq!(forgotten.interval_days, 1);
+        assert_eq!(forgotten.repetitions, 0);
+        assert_eq!(forgotten.lapses, 2);
+    }
+
+    #[test]
+    fn ease_does_not_drop_below_the_minimum() {
+        let mut current = schedule(1.4, 1, 0, 0);
+        for _ in 0..5 {
+            current = next_schedule(current, 3);
+        }
+
+        assert_eq!(current.ease_factor, MIN_EASE_FACTOR);
+    }
+}
@@ -0,0 +1,295 @@
+use crate::commands::notes::convert_blob_to_vec_f32;
+use crate::vector_math::cosine_similarity;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+const MAX_NGRAM: usize = 3;
+// Most frequent candidates embedded per note
+const MAX_CANDIDATES: usize = 150;
+const KEYPHRASES: usize = 10;
+// Relevance to the whole note versus to its best matching chunk
+const NOTE_WEIGHT: f32 = 0.6;
+// Maximal Marginal Relevance trade-off, 1.0 ignores diversity
+const MMR_LAMBDA: f32 = 0.7;
+
+const ENGLISH_STOPWORDS: [&str; 120] = [
+    "a", "about", "above", "after", "again", "against", "all", "also", "am", "an", "and", "any",
+    "are", "as", "at", "be", "because", "been", "before", "being", "below", "between", "both",
+    "but", "by", "can", "could", "did", "do", "does", "doing", "down", "during", "each", "few",
+    "for", "from", "further", "had", "has", "have", "having", "he", "her", "here", "hers", "him",
+    "his", "how", "i", "if", "in", "into", "is", "it", "its", "itself", "just", "me", "more",
+    "most", "my", "no", "nor", "not", "now", "of", "off", "on", "once", "only", "or", "other",
+    "our", "ours", "out", "over", "own", "same", "she", "should", "so", "some", "such", "than",
+    "that", "the", "their", "them", "then", "there", "these", "they", "this", "those", "through",
+    "to", "too", "under", "until", "up", "very", "was", "we", "were", "what", "when", "where",
+    "which", "while", "who", "whom", "why", "will", "with", "would", "you", "your", "yours",
+    "yourself",
+];
+
+const GERMAN_STOPWORDS: [&str; 121] = [
+    "aber", "alle", "allem", "allen", "aller", "alles", "als", "also", "am", "an", "ander",
+    "andere", "anderen", "auch", "auf", "aus", "bei", "bin", "bis", "bist", "da", "damit", "dann",
+    "das", "dass", "dein", "deine", "dem", "den", "denn", "der", "des", "dich", "die", "dies",
+    "diese", "diesem", "diesen", "dieser", "dieses", "dir", "doch", "dort", "du", "durch", "ein",
+    "eine", "einem", "einen", "einer", "eines", "er", "es", "etwas", "euch", "euer", "für",
+    "gegen", "hat", "hatte", "haben", "hier", "hin", "ich", "ihm", "ihn", "ihr", "ihre", "im",
+    "in", "ist", "jede", "jeder", "jetzt", "kann", "kein", "keine", "man", "mein", "meine", "mich",
+    "mir", "mit", "muss", "nach", "nicht", "nichts", "noch", "nun", "nur", "ob", "oder", "ohne",
+    "sehr", "sein", "seine", "sich", "sie", "sind", "so", "solche", "soll", "um", "und", "uns",
+    "unser", "unter", "viel", "vom", "von", "vor", "war", "waren", "was", "weil", "wenn", "wer",
+    "wie", "wir", "wird", "zu",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct Keyphrase {
+    pub phrase: String,
+    /// Similarity to the note, between -1 and 1
+    pub score: f64,
+}
+
+/// Stopwords of a language detected by `detect_language`. Notes without a
+/// detected language are taken as English. Candidates are words split on
+/// whitespace, so languages written without spaces such as Japanese or
+/// Chinese aren't supported, nor are those without a stopword list.
+fn stopwords(language: Option<&str>) -> Option<&'static [&'static str]> {
+    match language {
+        None | Some("eng") => Some(&ENGLISH_STOPWORDS),
+        Some("deu") => Some(&GERMAN_STOPWORDS),
+        Some(_) => None,
+    }
+}
+
+/// Runs of 1 to `MAX_NGRAM` words that don't cross punctuation or start or
+/// end with a stopword, the most frequent first. None for a language that
+/// isn't supported.
+fn candidate_phrases(content: &str, language: Option<&str>) -> Option<Vec<String>> {
+    let stopwords = stopwords(language)?;
+    let is_stopword = |word: &String| stopwords.contains(&word.as_str());
+
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+
+    let segments = content
+        .split(|c: char| !(c.is_alphanumeric() || c.is_whitespace() || c == '-' || c == '\''));
+
+    for segment in segments {
+        let words: Vec<String> = segment
+            .split_whitespace()
+            .map(|word| word.trim_matches(|c| c == '-' || c == '\'').to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        for n in 1..=MAX_NGRAM {
+            for window in words.windows(n) {
+                let first = &window[0];
+                let last = &window[n - 1];
+                if is_stopword(first) || is_stopword(last) {
+                    continue;
+                }
+                if window
+                    .iter()
+                    .all(|word| word.chars().all(|c| c.is_numeric()))
+                {
+                    continue;
+                }
+                if n == 1 && first.chars().count() < 3 {
+                    continue;
+                }
+
+                let order = counts.len();
+                counts.entry(window.join(" ")).or_insert((0, order)).0 += 1;
+            }
+        }
+    }
+
+    let mut candidates: Vec<(String, (usize, usize))> = counts.into_iter().collect();
+    // Ties go to the phrase seen first
+    candidates.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.1 .1.cmp(&b.1 .1)));
+    candidates.truncate(MAX_CANDIDATES);
+
+    Some(candidates.into_iter().map(|(phrase, _)| phrase).collect())
+}
+
+/// Pick `count` candidates by Maximal Marginal Relevance: each pick is the
+/// most relevant candidate that is not too similar to the previous picks
+fn select_by_mmr(relevance: &[f32], embeddings: &[Vec<f32>], count: usize) -> Vec<usize> {
+    let mut selected: Vec<usize> = vec![];
+
+    while selected.len() < count.min(relevance.len()) {
+        let best = (0..relevance.len())
+            .filter(|i| !selected.contains(i))
+            .map(|i| {
+                let redundancy = selected
+                    .iter()
+                    .map(|&j| cosine_similarity(&embeddings[i], &embeddings[j]))
+                    .fold(0.0, f32::max);
+                (
+                    i,
+                    MMR_LAMBDA * relevance[i] - (1.0 - MMR_LAMBDA) * redundancy,
+                )
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((i, _)) => selected.push(i),
+            None => break,
+        }
+    }
+
+    selected
+}
+
+/// Extract the phrases of a note that best describe it, KeyBERT style, and
+/// replace the stored ones
+pub async fn update_note_keyphrases(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<Vec<Keyphrase>, String> {
+    let db = &state.db;
+
+    let (content, average, language): (String, Option<Vec<u8>>, Option<String>) = sqlx::query_as(
+        "SELECT content, average_sentence_embedding, language FROM notes WHERE id = ?1",
+    )
+    .bind(note_id)
+    .fetch_one(db)
+    .await
+    .map_err(|e| format!("Failed to get note {}", e))?;
+
+    let chunk_rows: Vec<(Vec<u8>,)> =
+        sqlx::query_as("SELECT sentence_embedding FROM note_chunks WHERE note_id = ?1")
+            .bind(note_id)
+            .fetch_all(db)
+            .await
+            .map_err(|e| format!("Failed to get note chunks {}", e))?;
+    let chunk_embeddings: Vec<Vec<f32>> = chunk_rows
+        .into_iter()
+        .map(|(blob,)| convert_blob_to_vec_f32(blob).unwrap())
+        .collect();
+
+    // Notes in an unsupported language get no keyphrases rather than wrong ones
+    let candidates = candidate_phrases(&content, language.as_deref()).unwrap_or_default();
+
+    let keyphrases: Vec<Keyphrase> = match average {
+        Some(average) if !candidates.is_empty() => {
+            let note_embedding = convert_blob_to_vec_f32(average).unwrap();
+            let embeddings = state
+                .sentence_encoder
+                .encode(candidates.clone())
+                .await
+                .map_err(|e| format!("Failed to embed keyphrases {}", e))?;
+
+            let relevance: Vec<f32> = embeddings
+                .iter()
+                .map(|embedding| {
+                    let note_similarity = cosine_similarity(embedding, &note_embedding);
+                    // A note that has an embedding has chunks, but don't
+                    // let a missing one sink every candidate
+                    let chunk_similarity = if chunk_embeddings.is_empty() {
+                        note_similarity
+                    } else {
+                        chunk_embeddings
+                            .iter()
+                            .map(|chunk| cosine_similarity(embedding, chunk))
+                            .fold(f32::MIN, f32::max)
+                    };
+                    NOTE_WEIGHT * note_similarity + (1.0 - NOTE_WEIGHT) * chunk_similarity
+                })
+                .collect();
+
+            select_by_mmr(&relevance, &embeddings, KEYPHRASES)
+                .into_iter()
+                .map(|i| Keyphrase {
+                    phrase: candidates[i].clone(),
+                    score: relevance[i] as f64,
+                })
+                .collect()
+        }
+        _ => vec![],
+    };
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    sqlx::query("DELETE FROM note_keyphrases WHERE note_id = ?1")
+        .bind(note_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not delete keyphrases {}", e))?;
+
+    for (rank, keyphrase) in keyphrases.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO note_keyphrases (note_id, phrase, score, rank) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(note_id)
+        .bind(&keyphrase.phrase)
+        .bind(keyphrase.score)
+        .bind(rank as i64)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not insert keyphrase {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not save keyphrases {}", e))?;
+
+    Ok(keyphrases)
+}
+
+/// Stored keyphrases of a note, the most relevant first
+pub async fn get_stored_keyphrases(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<Vec<Keyphrase>, String> {
+    sqlx::query_as::<_, Keyphrase>(
+        "SELECT phrase, score FROM note_keyphrases WHERE note_id = ?1 ORDER BY rank",
+    )
+    .bind(note_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| format!("Failed to get keyphrases {}", e))
+}
+
+/// Phrases taken from the note itself that describe it best, unlike
+/// `get_similar_words` which may return words the note doesn't contain.
+/// Extracted on the fly for notes saved before keyphrases existed.
+#[tauri::command]
+pub async fn get_note_keyphrases(
+    state: tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<Vec<Keyphrase>, String> {
+    let keyphrases = get_stored_keyphrases(&state, note_id).await?;
+
+    if !keyphrases.is_empty() {
+        return Ok(keyphrases);
+    }
+
+    update_note_keyphrases(&state, note_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn german_stopwords_are_left_out() {
+        let candidates =
+            candidate_phrases("Die Katze und der Hund. Die Katze schläft.", Some("deu")).unwrap();
+        assert_eq!(candidates[0], "katze");
+        assert!(candidates.contains(&"katze schläft".to_string()));
+        assert!(!candidates.iter().any(|phrase| phrase.starts_with("die ")));
+        assert!(!candidates.contains(&"und".to_string()));
+    }
+
+    #[test]
+    fn unsupported_languages_have_no_candidates() {
+        assert_eq!(candidate_phrases("猫が好きです。", Some("jpn")), None);
+        assert!(candidate_phrases("The cat sat", None)
+            .unwrap()
+            .contains(&"cat sat".to_string()));
+    }
+}
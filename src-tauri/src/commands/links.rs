@@ -0,0 +1,568 @@
+use crate::commands::notes::update_note;
+use crate::commands::revisions::insert_note_revision;
+use crate::{AppState, Db};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use tauri::Manager;
+
+// [[Note title]], [[#42]] and [[Note title|shown text]]
+const LINK_PATTERN: &str = r"\[\[([^\[\]|]+)(\|[^\[\]]*)?\]\]";
+
+#[derive(Debug)]
+struct ParsedLink {
+    kind: &'static str,
+    target_text: String,
+    start_offset: i64,
+    end_offset: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct NoteLink {
+    id: i64,
+    source_note_id: i64,
+    source_title: Option<String>,
+    /// Missing while no note matches the link
+    target_note_id: Option<i64>,
+    target_title: Option<String>,
+    /// `title` or `id`
+    kind: String,
+    target_text: String,
+    /// Character offsets of the link in the source note content
+    start_offset: i64,
+    end_offset: i64,
+}
+
+/// A link target no note matches yet
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnresolvedLink {
+    kind: String,
+    target_text: String,
+    link_count: i64,
+    source_note_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GraphNode {
+    id: i64,
+    title: Option<String>,
+    /// Links from and to the note
+    link_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct GraphEdge {
+    source: i64,
+    target: i64,
+    /// Number of links from source to target
+    weight: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NoteGraph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+fn parse_links(content: &str) -> Vec<ParsedLink> {
+    let pattern = Regex::new(LINK_PATTERN).unwrap();
+
+    let mut links: Vec<ParsedLink> = vec![];
+    // Offsets are counted in characters, like the chunk offsets
+    let mut byte_offset = 0;
+    let mut char_offset = 0;
+
+    for captures in pattern.captures_iter(content) {
+        let whole = captures.get(0).unwrap();
+        let target = captures[1].trim();
+        if target.is_empty() {
+            continue;
+        }
+
+        char_offset += content[byte_offset..whole.start()].chars().count() as i64;
+        byte_offset = whole.start();
+        let length = whole.as_str().chars().count() as i64;
+
+        let (kind, target_text) = match target.strip_prefix('#') {
+            Some(id) if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) => ("id", id),
+            _ => ("title", target),
+        };
+
+        links.push(ParsedLink {
+            kind,
+            target_text: target_text.to_string(),
+            start_offset: char_offset,
+            end_offset: char_offset + length,
+        });
+    }
+
+    links
+}
+
+/// Replace the links stored for a note with the ones in its content. New
+/// title links resolve to the oldest note with that title.
+pub async fn update_note_links(db: &Db, note_id: i64, content: &str) -> Result<(), String> {
+    let links = parse_links(content);
+
+    // Title links keep the note they resolved to, even when its generated
+    // title changed since
+    let resolved: Vec<(String, i64)> = sqlx::query_as(
+        "
+        SELECT note_links.target_text, note_links.target_note_id
+        FROM note_links
+        JOIN notes ON notes.id = note_links.target_note_id
+        WHERE note_links.source_note_id = ?1 AND note_links.kind = 'title' AND notes.deleted_at IS NULL
+        ",
+    )
+    .bind(note_id)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get note links {}", e))?;
+    let resolved: HashMap<String, i64> = resolved
+        .into_iter()
+        .map(|(target_text, target_note_id)| (target_text.to_lowercase(), target_note_id))
+        .collect();
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    sqlx::query("DELETE FROM note_links WHERE source_note_id = ?1")
+        .bind(note_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not delete note links {}", e))?;
+
+    for link in &links {
+        sqlx::query(
+            "
+            INSERT INTO note_links (source_note_id, target_note_id, kind, target_text, start_offset, end_offset)
+            VALUES (
+                ?1,
+                CASE WHEN ?2 = 'id'
+                    THEN (SELECT id FROM notes WHERE id = CAST(?3 AS INTEGER) AND deleted_at IS NULL)
+                    ELSE COALESCE(?6, (
+                        SELECT id FROM notes
+                        WHERE title = ?3 COLLATE NOCASE AND deleted_at IS NULL
+                        ORDER BY id LIMIT 1
+                    ))
+                END,
+                ?2, ?3, ?4, ?5
+            )
+            ",
+        )
+        .bind(note_id)
+        .bind(link.kind)
+        .bind(&link.target_text)
+        .bind(link.start_offset)
+        .bind(link.end_offset)
+        .bind(match link.kind {
+            "title" => resolved.get(&link.target_text.to_lowercase()).copied(),
+            _ => None,
+        })
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not save note link {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not save note links {}", e))?;
+
+    Ok(())
+}
+
+/// Point unresolved title links matching the title of a note at the note.
+/// Links already resolved keep their note, as generated titles change with
+/// most edits.
+pub async fn resolve_links_to_note_title(
+    db: &Db,
+    note_id: i64,
+    title: Option<&str>,
+) -> Result<(), String> {
+    sqlx::query(
+        "
+        UPDATE note_links SET target_note_id = ?1
+        WHERE target_note_id IS NULL AND kind = 'title' AND target_text = ?2 COLLATE NOCASE
+        ",
+    )
+    .bind(note_id)
+    .bind(title)
+    .execute(db)
+    .await
+    .map_err(|e| format!("could not update note links {}", e))?;
+
+    Ok(())
+}
+
+/// Point unresolved links at a note taken out of the trash, `[[#id]]` links
+/// to it as well as title links matching its title. Links written while it
+/// was in the trash couldn't resolve to it.
+pub async fn resolve_links_to_note(db: &Db, note_id: i64) -> Result<(), String> {
+    let title: Option<(Option<String>,)> =
+        sqlx::query_as("SELECT title FROM notes WHERE id = ?1 AND deleted_at IS NULL")
+            .bind(note_id)
+            .fetch_optional(db)
+            .await
+            .map_err(|e| format!("Failed to get note {}", e))?;
+    let title = match title {
+        Some((title,)) => title,
+        None => return Ok(()),
+    };
+
+    sqlx::query(
+        "
+        UPDATE note_links SET target_note_id = ?1
+        WHERE target_note_id IS NULL AND kind = 'id' AND target_text = ?2
+        ",
+    )
+    .bind(note_id)
+    .bind(note_id.to_string())
+    .execute(db)
+    .await
+    .map_err(|e| format!("could not update note links {}", e))?;
+
+    resolve_links_to_note_title(db, note_id, title.as_deref()).await
+}
+
+/// Rewrite links written with the old title of a note the user renamed, so
+/// they read as the new title. Rewritten notes are saved like any edit, with
+/// a revision and enrichment.
+pub async fn rename_links_to_note(
+    app_handle: &tauri::AppHandle,
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+    old_title: Option<&str>,
+    new_title: &str,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    if let Some(old_title) = old_title.filter(|old_title| *old_title != new_title) {
+        let sources: Vec<(i64, String)> = sqlx::query_as(
+            "
+            SELECT DISTINCT notes.id, notes.content
+            FROM note_links
+            JOIN notes ON notes.id = note_links.source_note_id
+            WHERE note_links.target_note_id = ?1 AND note_links.kind = 'title' AND note_links.target_text = ?2
+                AND notes.deleted_at IS NULL
+            ",
+        )
+        .bind(note_id)
+        .bind(old_title)
+        .fetch_all(db)
+        .await
+        .map_err(|e| format!("Failed to get linking notes {}", e))?;
+
+        let pattern = Regex::new(LINK_PATTERN).unwrap();
+
+        for (source_id, content) in sources {
+            let rewritten = pattern.replace_all(&content, |captures: &Captures| {
+                if captures[1].trim().eq_ignore_ascii_case(old_title) {
+                    let alias = captures.get(2).map_or("", |alias| alias.as_str());
+                    format!("[[{}{}]]", new_title, alias)
+                } else {
+                    captures[0].to_string()
+                }
+            });
+            if rewritten == content {
+                continue;
+            }
+
+            insert_note_revision(
+                db,
+                source_id,
+                true,
+                Some(&format!("Before renaming links to {}", old_title)),
+            )
+            .await?;
+            let _ = app_handle.emit_all("refetch_note_revisions", "");
+
+            update_note(
+                app_handle.clone(),
+                state.clone(),
+                source_id,
+                rewritten.into_owned(),
+            )
+            .await?;
+        }
+    }
+
+    resolve_links_to_note_title(db, note_id, Some(new_title)).await
+}
+
+const NOTE_LINK_QUERY: &str = "
+    SELECT
+        note_links.id,
+        note_links.source_note_id,
+        sources.title AS source_title,
+        note_links.target_note_id,
+        targets.title AS target_title,
+        note_links.kind,
+        note_links.target_text,
+        note_links.start_offset,
+        note_links.end_offset
+    FROM note_links
+    JOIN notes sources ON sources.id = note_links.source_note_id
+    LEFT JOIN notes targets ON targets.id = note_links.target_note_id
+";
+
+/// Links written in a note, in the order they appear
+#[tauri::command]
+pub async fn get_outgoing_links(
+    state: tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<Vec<NoteLink>, String> {
+    sqlx::query_as::<_, NoteLink>(&format!(
+        "{} WHERE note_links.source_note_id = ?1 ORDER BY note_links.start_offset",
+        NOTE_LINK_QUERY
+    ))
+    .bind(note_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| format!("Failed to get outgoing links {}", e))
+}
+
+/// Links from other notes to a note, the most recently edited notes first
+#[tauri::command]
+pub async fn get_backlinks(
+    state: tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<Vec<NoteLink>, String> {
+    sqlx::query_as::<_, NoteLink>(&format!(
+        "
+        {}
+        WHERE note_links.target_note_id = ?1
+            AND note_links.source_note_id != ?1
+            AND sources.deleted_at IS NULL
+        ORDER BY sources.updated_at DESC, note_links.start_offset
+        ",
+        NOTE_LINK_QUERY
+    ))
+    .bind(note_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| format!("Failed to get backlinks {}", e))
+}
+
+/// Link targets without a note, or whose note is in the trash, so they can be
+/// created. Links in trashed notes are left out. The most linked first.
+#[tauri::command]
+pub async fn get_unresolved_links(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<UnresolvedLink>, String> {
+    let rows: Vec<(String, String, i64)> = sqlx::query_as(
+        "
+        SELECT note_links.kind, note_links.target_text, note_links.source_note_id
+        FROM note_links
+        JOIN notes sources ON sources.id = note_links.source_note_id
+        LEFT JOIN notes targets ON targets.id = note_links.target_note_id
+        WHERE (targets.id IS NULL OR targets.deleted_at IS NOT NULL)
+            AND sources.deleted_at IS NULL
+        ORDER BY note_links.source_note_id
+        ",
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| format!("Failed to get unresolved links {}", e))?;
+
+    // Titles differing only in case are the same link
+    let mut grouped: BTreeMap<(String, String), UnresolvedLink> = BTreeMap::new();
+    for (kind, target_text, source_note_id) in rows {
+        let link = grouped
+            .entry((kind.clone(), target_text.to_lowercase()))
+            .or_insert_with(|| UnresolvedLink {
+                kind,
+                target_text,
+                link_count: 0,
+                source_note_ids: vec![],
+            });
+        link.link_count += 1;
+        if !link.source_note_ids.contains(&source_note_id) {
+            link.source_note_ids.push(source_note_id);
+        }
+    }
+
+    let mut links: Vec<UnresolvedLink> = grouped.into_values().collect();
+    links.sort_by(|a, b| b.link_count.cmp(&a.link_count));
+
+    Ok(links)
+}
+
+/// Every note with the links between them
+#[tauri::command]
+pub async fn get_graph(state: tauri::State<'_, AppState>) -> Result<NoteGraph, String> {
+    let db = &state.db;
+
+    let notes: Vec<(i64, Option<String>)> =
+        sqlx::query_as("SELECT id, title FROM notes WHERE deleted_at IS NULL")
+            .fetch_all(db)
+            .await
+            .map_err(|e| format!("Failed to get notes {}", e))?;
+
+    let edges: Vec<GraphEdge> = sqlx::query_as::<_, GraphEdge>(
+        "
+        SELECT note_links.source_note_id AS source, note_links.target_note_id AS target, COUNT(*) AS weight
+        FROM note_links
+        JOIN notes sources ON sources.id = note_links.source_note_id
+        JOIN notes targets ON targets.id = note_links.target_note_id
+        WHERE note_links.target_note_id != note_links.source_note_id
+            AND sources.deleted_at IS NULL
+            AND targets.deleted_at IS NULL
+        GROUP BY note_links.source_note_id, note_links.target_note_id
+        ",
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get note links {}", e))?;
+
+    let mut link_counts: HashMap<i64, i64> = HashMap::new();
+    for edge in &edges {
+        *link_counts.entry(edge.source).or_default() += edge.weight;
+        *link_counts.entry(edge.target).or_default() += edge.weight;
+    }
+
+    let nodes = notes
+        .into_iter()
+        .map(|(id, title)| GraphNode {
+            id,
+            title,
+            link_count: link_counts.get(&id).copied().unwrap_or(0),
+        })
+        .collect();
+
+    Ok(NoteGraph { nodes, edges })
+}
+
+/// Parse the links of every note again
+#[tauri::command]
+pub async fn reindex_links(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let notes: Vec<(i64, String)> = sqlx::query_as("SELECT id, content FROM notes")
+        .fetch_all(db)
+        .await
+        .map_err(|e| format!("Failed to get notes {}", e))?;
+
+    for (note_id, content) in notes {
+        update_note_links(db, note_id, &content).await?;
+    }
+
+    let _ = app_handle.emit_all("refetch_links", "");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::trash::{trash_note, untrash_note};
+    use crate::setup_test_db;
+
+    #[test]
+    fn parses_title_and_id_links() {
+        let links = parse_links("See [[Rust notes]] and [[#42]]");
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].kind, "title");
+        assert_eq!(links[0].target_text, "Rust notes");
+        assert_eq!(links[1].kind, "id");
+        assert_eq!(links[1].target_text, "42");
+    }
+
+    #[test]
+    fn ids_must_be_numbers() {
+        let links = parse_links("[[#abc]] [[#]] [[#12a]]");
+
+        let parsed: Vec<(&str, &str)> = links
+            .iter()
+            .map(|link| (link.kind, link.target_text.as_str()))
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![("title", "#abc"), ("title", "#"), ("title", "#12a")]
+        );
+    }
+
+    #[test]
+    fn aliases_are_not_part_of_the_target() {
+        let links = parse_links("[[ Rust notes | my notes ]] and [[#7|seven]]");
+
+        assert_eq!(links[0].kind, "title");
+        assert_eq!(links[0].target_text, "Rust notes");
+        assert_eq!(links[1].kind, "id");
+        assert_eq!(links[1].target_text, "7");
+    }
+
+    #[test]
+    fn skips_empty_targets() {
+        assert!(parse_links("[[ ]] and [[ |alias]] and [[]]").is_empty());
+    }
+
+    #[test]
+    fn offsets_count_characters() {
+        let content = "Café → [[Target]] then [[#3|ñ]]";
+        let links = parse_links(content);
+
+        let chars: Vec<char> = content.chars().collect();
+        let text = |link: &ParsedLink| -> String {
+            chars[link.start_offset as usize..link.end_offset as usize]
+                .iter()
+                .collect()
+        };
+        assert_eq!(links[0].start_offset, 7);
+        assert_eq!(text(&links[0]), "[[Target]]");
+        assert_eq!(text(&links[1]), "[[#3|ñ]]");
+    }
+
+    async fn link_targets(db: &Db, source_id: i64) -> Vec<Option<i64>> {
+        sqlx::query_scalar(
+            "SELECT target_note_id FROM note_links WHERE source_note_id = ?1 ORDER BY start_offset",
+        )
+        .bind(source_id)
+        .fetch_all(db)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn links_written_while_a_note_was_trashed_resolve_on_restore() {
+        let db = setup_test_db().await;
+
+        let target_id: i64 = sqlx::query_scalar(
+            "INSERT INTO notes (content, title) VALUES ('', 'Rust') RETURNING id",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        let source_id: i64 =
+            sqlx::query_scalar("INSERT INTO notes (content) VALUES ('') RETURNING id")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+
+        trash_note(&mut *db.acquire().await.unwrap(), target_id)
+            .await
+            .unwrap();
+
+        let content = format!("[[rust]] and [[#{}]]", target_id);
+        update_note_links(&db, source_id, &content).await.unwrap();
+
+        assert_eq!(link_targets(&db, source_id).await, vec![None, None]);
+
+        untrash_note(&mut *db.acquire().await.unwrap(), target_id)
+            .await
+            .unwrap();
+        resolve_links_to_note(&db, target_id).await.unwrap();
+
+        assert_eq!(
+            link_targets(&db, source_id).await,
+            vec![Some(target_id), Some(target_id)]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use crate::commands::notes::{convert_blob_to_vec_f32, get_note_embeddings};
+use crate::commands::settings::{read_setting_or, write_setting, NOTE_MAP_CHANGES_SINCE_FIT};
+use crate::commands::tags::Tag;
+use crate::vector_math::{pca, project};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use tauri::Manager;
+
+const PCA_ITERATIONS: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NoteMapPoint {
+    note_id: i64,
+    x: f64,
+    y: f64,
+    title: Option<String>,
+    topic_id: Option<i64>,
+    tags: Vec<Tag>,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct NoteMapPointWithTag {
+    note_id: i64,
+    x: f64,
+    y: f64,
+    title: Option<String>,
+    topic_id: Option<i64>,
+    tag_id: Option<i64>,
+    tag_name: Option<String>,
+    tag_parent_id: Option<i64>,
+    tag_path: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct NoteMapProjection {
+    mean: Vec<u8>,
+    axes: Vec<u8>,
+}
+
+/// Fit a 2D PCA projection on all note embeddings and store every note's
+/// coordinates
+pub async fn fit_note_map(state: &tauri::State<'_, AppState>) -> Result<(), String> {
+    let db = &state.db;
+
+    let notes = get_note_embeddings(db).await?;
+    let vectors: Vec<&Vec<f32>> = notes.iter().map(|(_, embedding)| embedding).collect();
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    sqlx::query("DELETE FROM note_map_points; DELETE FROM note_map_projection;")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not delete note map {}", e))?;
+
+    if let Some((mean, axes)) = pca(&vectors, 2, PCA_ITERATIONS) {
+        sqlx::query("INSERT INTO note_map_projection (id, mean, axes) VALUES (1, ?1, ?2)")
+            .bind(serde_json::to_string(&mean).unwrap())
+            .bind(serde_json::to_string(&axes).unwrap())
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("could not save note map projection {}", e))?;
+
+        for (note_id, embedding) in &notes {
+            let coordinates = project(embedding, &mean, &axes);
+
+            sqlx::query("INSERT INTO note_map_points (note_id, x, y) VALUES (?1, ?2, ?3)")
+                .bind(note_id)
+                .bind(coordinates.first().copied().unwrap_or(0.0))
+                .bind(coordinates.get(1).copied().unwrap_or(0.0))
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("could not save note map point {}", e))?;
+        }
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not save note map {}", e))?;
+
+    write_setting(db, NOTE_MAP_CHANGES_SINCE_FIT, "0").await?;
+
+    Ok(())
+}
+
+/// Place a new or changed note on the map with the stored projection. The
+/// projection is fitted again once enough notes changed since the last fit.
+pub async fn update_note_map_point(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let projection: Option<NoteMapProjection> = sqlx::query_as::<_, NoteMapProjection>(
+        "SELECT mean, axes FROM note_map_projection WHERE id = 1",
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| format!("Failed to get note map projection {}", e))?;
+
+    let (note_count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM notes WHERE deleted_at IS NULL")
+            .fetch_one(db)
+            .await
+            .map_err(|e| format!("Failed to count notes {}", e))?;
+
+    let changes = read_setting_or(db, NOTE_MAP_CHANGES_SINCE_FIT, 0i64).await + 1;
+    let max_changes = (note_count / 4).max(10);
+
+    let projection = match projection {
+        Some(projection) if changes <= max_changes => projection,
+        _ => return fit_note_map(state).await,
+    };
+
+    write_setting(db, NOTE_MAP_CHANGES_SINCE_FIT, &changes.to_string()).await?;
+
+    let mean = convert_blob_to_vec_f32(projection.mean).unwrap();
+    let axes: Vec<Vec<f32>> =
+        serde_json::from_slice(&projection.axes).map_err(|e| e.to_string())?;
+
+    let (blob,): (Vec<u8>,) =
+        sqlx::query_as("SELECT average_sentence_embedding FROM notes WHERE id = ?1")
+            .bind(note_id)
+            .fetch_one(db)
+            .await
+            .map_err(|e| format!("Failed to get note {}", e))?;
+    let coordinates = project(&convert_blob_to_vec_f32(blob).unwrap(), &mean, &axes);
+
+    sqlx::query("INSERT OR REPLACE INTO note_map_points (note_id, x, y) VALUES (?1, ?2, ?3)")
+        .bind(note_id)
+        .bind(coordinates.first().copied().unwrap_or(0.0))
+        .bind(coordinates.get(1).copied().unwrap_or(0.0))
+        .execute(db)
+        .await
+        .map_err(|e| format!("could not save note map point {}", e))?;
+
+    Ok(())
+}
+
+/// 2D coordinates of every note with its tags and topic, for drawing a map of
+/// the vault
+#[tauri::command]
+pub async fn get_note_map(state: tauri::State<'_, AppState>) -> Result<Vec<NoteMapPoint>, String> {
+    let db = &state.db;
+
+    let (fitted,): (bool,) =
+        sqlx::query_as("SELECT EXISTS (SELECT 1 FROM note_map_projection WHERE id = 1)")
+            .fetch_one(db)
+            .await
+            .map_err(|e| format!("Failed to get note map projection {}", e))?;
+
+    if !fitted {
+        fit_note_map(&state).await?;
+    }
+
+    let rows: Vec<NoteMapPointWithTag> = sqlx::query_as::<_, NoteMapPointWithTag>(
+        "
+        SELECT
+            p.note_id, p.x, p.y, n.title, ntp.topic_id,
+            t.id AS tag_id, t.name AS tag_name, t.parent_id AS tag_parent_id, tp.path AS tag_path
+        FROM note_map_points p
+        JOIN notes n ON n.id = p.note_id
+        LEFT JOIN notes_to_topics ntp ON ntp.note_id = p.note_id
+        LEFT JOIN notes_to_tags nt ON nt.note_id = p.note_id
+        LEFT JOIN tags t ON t.id = nt.tag_id
+        LEFT JOIN tag_paths tp ON tp.id = t.id
+        WHERE n.deleted_at IS NULL
+        ",
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get note map {}", e))?;
+
+    // Process results to group tags under each point
+    let mut points_map: HashMap<i64, NoteMapPoint> = HashMap::new();
+    for row in rows {
+        let entry = points_map.entry(row.note_id).or_insert(NoteMapPoint {
+            note_id: row.note_id,
+            x: row.x,
+            y: row.y,
+            title: row.title.clone(),
+            topic_id: row.topic_id,
+            tags: vec![],
+        });
+
+        if let (Some(id), Some(name), Some(path)) = (row.tag_id, row.tag_name, row.tag_path) {
+            entry.tags.push(Tag {
+                id,
+                name,
+                parent_id: row.tag_parent_id,
+                path,
+            });
+        }
+    }
+
+    Ok(points_map.into_values().collect())
+}
+
+#[tauri::command]
+pub async fn recompute_note_map(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    fit_note_map(&state).await?;
+
+    let _ = app_handle.emit_all("refetch_note_map", "");
+
+    Ok(())
+}
@@ -1,2 +1,22 @@
+pub mod ask;
+pub mod comparison;
+pub mod contradictions;
+pub mod duplicates;
+pub mod enrichment;
+pub mod entities;
+pub mod flashcards;
+pub mod keyphrases;
+pub mod links;
+pub mod map;
+pub mod notebooks;
 pub mod notes;
+pub mod related;
+pub mod resurface;
+pub mod revisions;
+pub mod settings;
+pub mod summaries;
+pub mod tag_suggestions;
 pub mod tags;
+pub mod timeline;
+pub mod topics;
+pub mod trash;
@@ -0,0 +1,336 @@
+use crate::{AppState, Db};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use tauri::Manager;
+
+// Created by the migration, notes end up here when their notebook is gone
+pub const DEFAULT_NOTEBOOK_ID: i64 = 1;
+
+#[derive(Debug, FromRow)]
+struct NotebookRow {
+    id: i64,
+    name: String,
+    parent_id: Option<i64>,
+    created_at: i64,
+    note_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Notebook {
+    id: i64,
+    name: String,
+    parent_id: Option<i64>,
+    created_at: i64,
+    /// Notes directly in the notebook, leaving out archived and trashed ones
+    note_count: i64,
+    /// Notes in the notebook and all of its sub-notebooks
+    total_note_count: i64,
+}
+
+/// What happens to the notes of a deleted notebook
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletedNotebookNotes {
+    Move,
+    Trash,
+}
+
+/// Subquery selecting the ids of a notebook and all notebooks below it.
+/// `notebook_id` is the SQL expression of the notebook, e.g. `?2` or `4`.
+pub fn notebook_subtree_query(notebook_id: &str) -> String {
+    format!(
+        "
+        WITH RECURSIVE subtree(id) AS (
+            SELECT {}
+            UNION ALL
+            SELECT notebooks.id FROM notebooks JOIN subtree ON notebooks.parent_id = subtree.id
+        )
+        SELECT id FROM subtree
+        ",
+        notebook_id
+    )
+}
+
+async fn get_notebook_subtree(db: &Db, notebook_id: i64) -> Result<Vec<i64>, String> {
+    let ids: Vec<(i64,)> = sqlx::query_as(&notebook_subtree_query("?1"))
+        .bind(notebook_id)
+        .fetch_all(db)
+        .await
+        .map_err(|e| format!("Failed to get notebooks {}", e))?;
+
+    Ok(ids.into_iter().map(|(id,)| id).collect())
+}
+
+pub(crate) async fn ensure_notebook_exists(db: &Db, notebook_id: i64) -> Result<(), String> {
+    let found: Option<(i64,)> = sqlx::query_as("SELECT id FROM notebooks WHERE id = ?1")
+        .bind(notebook_id)
+        .fetch_optional(db)
+        .await
+        .map_err(|e| format!("Failed to get notebook {}", e))?;
+
+    match found {
+        Some(_) => Ok(()),
+        None => Err(format!("No notebook found with id: {}", notebook_id)),
+    }
+}
+
+/// Every notebook with its note counts. Nesting is given by `parent_id`.
+#[tauri::command]
+pub async fn get_notebooks(state: tauri::State<'_, AppState>) -> Result<Vec<Notebook>, String> {
+    let rows: Vec<NotebookRow> = sqlx::query_as::<_, NotebookRow>(
+        "
+        SELECT
+            notebooks.id,
+            notebooks.name,
+            notebooks.parent_id,
+            notebooks.created_at,
+            COUNT(notes.id) AS note_count
+        FROM notebooks
+        LEFT JOIN notes ON notes.notebook_id = notebooks.id
+            AND notes.deleted_at IS NULL
+            AND notes.archived = 0
+        GROUP BY notebooks.id
+        ORDER BY notebooks.name COLLATE NOCASE
+        ",
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| format!("Failed to get notebooks {}", e))?;
+
+    let mut children: HashMap<i64, Vec<i64>> = HashMap::new();
+    let mut note_counts: HashMap<i64, i64> = HashMap::new();
+    for row in &rows {
+        if let Some(parent_id) = row.parent_id {
+            children.entry(parent_id).or_default().push(row.id);
+        }
+        note_counts.insert(row.id, row.note_count);
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let mut total_note_count = 0;
+            let mut stack = vec![row.id];
+            while let Some(id) = stack.pop() {
+                total_note_count += note_counts.get(&id).copied().unwrap_or(0);
+                if let Some(child_ids) = children.get(&id) {
+                    stack.extend(child_ids);
+                }
+            }
+
+            Notebook {
+                id: row.id,
+                name: row.name,
+                parent_id: row.parent_id,
+                created_at: row.created_at,
+                note_count: row.note_count,
+                total_note_count,
+            }
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn create_notebook(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    name: String,
+    parent_id: Option<i64>,
+) -> Result<i64, String> {
+    let db = &state.db;
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Notebook name can not be empty".to_string());
+    }
+    if let Some(parent_id) = parent_id {
+        ensure_notebook_exists(db, parent_id).await?;
+    }
+
+    let inserted = sqlx::query("INSERT INTO notebooks (name, parent_id) VALUES (?1, ?2)")
+        .bind(name)
+        .bind(parent_id)
+        .execute(db)
+        .await
+        .map_err(|e| format!("could not create notebook {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_notebooks", "");
+
+    Ok(inserted.last_insert_rowid())
+}
+
+#[tauri::command]
+pub async fn rename_notebook(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    name: String,
+) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Notebook name can not be empty".to_string());
+    }
+
+    sqlx::query("UPDATE notebooks SET name = ?1 WHERE id = ?2")
+        .bind(name)
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| format!("could not rename notebook {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_notebooks", "");
+
+    Ok(())
+}
+
+/// Put a notebook under another one, or at the top level without
+/// `parent_id`. A notebook can't move below itself, and the default notebook
+/// stays at the top level.
+#[tauri::command]
+pub async fn move_notebook(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    parent_id: Option<i64>,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    // Below another notebook it would go when that one is deleted
+    if id == DEFAULT_NOTEBOOK_ID {
+        return Err("The default notebook can not be moved".to_string());
+    }
+
+    if let Some(parent_id) = parent_id {
+        ensure_notebook_exists(db, parent_id).await?;
+        if get_notebook_subtree(db, id).await?.contains(&parent_id) {
+            return Err(format!(
+                "Notebook {} can not be moved into itself or its sub-notebooks",
+                id
+            ));
+        }
+    }
+
+    sqlx::query("UPDATE notebooks SET parent_id = ?1 WHERE id = ?2")
+        .bind(parent_id)
+        .bind(id)
+        .execute(db)
+        .await
+        .map_err(|e| format!("could not move notebook {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_notebooks", "");
+
+    Ok(())
+}
+
+/// Delete a notebook with its sub-notebooks. Their notes move to
+/// `target_notebook_id`, by default the parent notebook or the default one,
+/// and with `trash` go to the trash as well.
+#[tauri::command]
+pub async fn delete_notebook(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    notes: DeletedNotebookNotes,
+    target_notebook_id: Option<i64>,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    if id == DEFAULT_NOTEBOOK_ID {
+        return Err("The default notebook can not be deleted".to_string());
+    }
+
+    let (parent_id,): (Option<i64>,) =
+        sqlx::query_as("SELECT parent_id FROM notebooks WHERE id = ?1")
+            .bind(id)
+            .fetch_one(db)
+            .await
+            .map_err(|e| format!("Failed to get notebook {}", e))?;
+
+    let subtree = get_notebook_subtree(db, id).await?;
+    if subtree.contains(&DEFAULT_NOTEBOOK_ID) {
+        return Err("The default notebook can not be deleted".to_string());
+    }
+    let target_notebook_id = target_notebook_id
+        .or(parent_id)
+        .unwrap_or(DEFAULT_NOTEBOOK_ID);
+    if subtree.contains(&target_notebook_id) {
+        return Err("Notes can not be moved into a notebook being deleted".to_string());
+    }
+    ensure_notebook_exists(db, target_notebook_id).await?;
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    let now = chrono::Utc::now().timestamp();
+
+    for notebook_id in &subtree {
+        // Notes already in the trash move as well, so restoring them lands
+        // them in a notebook that exists
+        if notes == DeletedNotebookNotes::Trash {
+            sqlx::query(
+                "UPDATE notes SET deleted_at = ?1 WHERE notebook_id = ?2 AND deleted_at IS NULL",
+            )
+            .bind(now)
+            .bind(notebook_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("could not trash notes {}", e))?;
+        }
+
+        sqlx::query("UPDATE notes SET notebook_id = ?1 WHERE notebook_id = ?2")
+            .bind(target_notebook_id)
+            .bind(notebook_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("could not move notes {}", e))?;
+    }
+
+    // Sub-notebooks go with their parent
+    sqlx::query("DELETE FROM notebooks WHERE id = ?1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not delete notebook {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not delete notebook {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_notebooks", "");
+    let _ = app_handle.emit_all("refetch_notes", "");
+    if notes == DeletedNotebookNotes::Trash {
+        let _ = app_handle.emit_all("refetch_trash", "");
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn move_notes_to_notebook(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    note_ids: Vec<i64>,
+    notebook_id: i64,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    ensure_notebook_exists(db, notebook_id).await?;
+
+    for note_id in note_ids {
+        sqlx::query("UPDATE notes SET notebook_id = ?1 WHERE id = ?2")
+            .bind(notebook_id)
+            .bind(note_id)
+            .execute(db)
+            .await
+            .map_err(|e| format!("could not move note {}", e))?;
+    }
+
+    let _ = app_handle.emit_all("refetch_notes", "");
+    let _ = app_handle.emit_all("refetch_notebooks", "");
+
+    Ok(())
+}
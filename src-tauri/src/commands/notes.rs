@@ -1,5 +1,19 @@
-use crate::commands::tags::Tag;
-use crate::{AppState, SentenceEncoder};
+use crate::commands::enrichment::spawn_note_enrichment;
+use crate::commands::links::update_note_links;
+use crate::commands::notebooks::{
+    ensure_notebook_exists, notebook_subtree_query, DEFAULT_NOTEBOOK_ID,
+};
+use crate::commands::revisions::snapshot_note_before_update;
+use crate::commands::settings::{
+    read_setting_or, write_setting, CENTROID_WEIGHTING, INDEXED_EMBEDDING_BACKEND,
+    LANGUAGES_BACKFILLED, NOTE_CENTROIDS_K,
+};
+use crate::commands::tags::{tag_filter_condition, Tag};
+use crate::commands::trash::trash_note;
+use crate::language_detector::detect_language;
+use crate::sentence_encoder::SentenceEncoderBackend;
+use crate::vector_math::{kmeans, weighted_centroid};
+use crate::{AppState, Db, SentenceEncoder};
 use futures::future::join_all;
 use futures::TryStreamExt;
 use langchain_rust::text_splitter::{MarkdownSplitter, SplitterOptions, TextSplitter};
@@ -7,6 +21,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, Row};
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::str::FromStr;
 use std::time::Instant;
 use tauri::Manager;
 
@@ -14,15 +29,30 @@ use tauri::Manager;
 pub struct Word {
     id: i64,
     text: String,
+    distance: f64,
+}
+
+#[derive(Debug, FromRow)]
+struct NoteMatch {
+    note_id: i64,
+    distance: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Note {
-    id: i64,
-    content: String,
+    pub id: i64,
+    pub content: String,
+    pub title: Option<String>,
+    summary: Option<String>,
+    title_is_custom: bool,
     average_sentence_embedding: Vec<f32>,
-    created_at: i64,
-    updated_at: i64,
+    language: Option<String>,
+    pinned: bool,
+    archived: bool,
+    favourite: bool,
+    notebook_id: i64,
+    pub created_at: i64,
+    pub updated_at: i64,
     tags: Vec<Tag>,
 }
 
@@ -30,13 +60,37 @@ pub struct Note {
 struct NoteWithTag {
     id: i64,
     content: String,
+    title: Option<String>,
+    summary: Option<String>,
+    title_is_custom: bool,
     average_sentence_embedding: Vec<u8>,
+    language: Option<String>,
+    pinned: bool,
+    archived: bool,
+    favourite: bool,
+    notebook_id: i64,
     created_at: i64,
     updated_at: i64,
-    tag_id: Option<String>,
+    tag_id: Option<i64>,
+    tag_name: Option<String>,
+    tag_parent_id: Option<i64>,
+    tag_path: Option<String>,
 }
 
-fn convert_blob_to_vec_f32(blob: Vec<u8>) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+const NOTE_WITH_TAG_COLUMNS: &str = "n.id, n.content, n.title, n.summary, n.title_is_custom, n.average_sentence_embedding, n.language, n.pinned, n.archived, n.favourite, n.notebook_id, n.created_at, n.updated_at, t.id as tag_id, t.name as tag_name, t.parent_id as tag_parent_id, tp.path as tag_path";
+
+impl NoteWithTag {
+    fn tag(&self) -> Option<Tag> {
+        Some(Tag {
+            id: self.tag_id?,
+            name: self.tag_name.clone()?,
+            parent_id: self.tag_parent_id,
+            path: self.tag_path.clone()?,
+        })
+    }
+}
+
+pub fn convert_blob_to_vec_f32(blob: Vec<u8>) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
     // Convert the blob (Vec<u8>) to a string
     let json_string = String::from_utf8(blob)?;
 
@@ -50,15 +104,31 @@ impl<'r> FromRow<'r, sqlx::sqlite::SqliteRow> for Note {
     fn from_row(row: &'r sqlx::sqlite::SqliteRow) -> Result<Self, sqlx::Error> {
         let id: i64 = row.try_get("id")?;
         let content: String = row.try_get("content")?;
+        let title: Option<String> = row.try_get("title")?;
+        let summary: Option<String> = row.try_get("summary")?;
+        let title_is_custom: bool = row.try_get("title_is_custom")?;
         let blob: Vec<u8> = row.try_get("average_sentence_embedding")?;
         let average_sentence_embedding = convert_blob_to_vec_f32(blob).unwrap();
+        let language: Option<String> = row.try_get("language")?;
+        let pinned: bool = row.try_get("pinned")?;
+        let archived: bool = row.try_get("archived")?;
+        let favourite: bool = row.try_get("favourite")?;
+        let notebook_id: i64 = row.try_get("notebook_id")?;
         let created_at: i64 = row.try_get("created_at")?;
         let updated_at: i64 = row.try_get("updated_at")?;
 
         Ok(Note {
             id,
             content,
+            title,
+            summary,
+            title_is_custom,
             average_sentence_embedding,
+            language,
+            pinned,
+            archived,
+            favourite,
+            notebook_id,
             created_at,
             updated_at,
             tags: vec![], // Initialize with an empty vector
@@ -66,6 +136,9 @@ impl<'r> FromRow<'r, sqlx::sqlite::SqliteRow> for Note {
     }
 }
 
+const KMEANS_MAX_ITERATIONS: usize = 20;
+const SEARCH_RESULTS: i64 = 10;
+
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 struct NoteChunk {
     id: u16,
@@ -73,11 +146,55 @@ struct NoteChunk {
     note_id: u16,
 }
 
+/// A chunk returned by a KNN search over `vec_note_chunks`
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct NoteChunkMatch {
+    pub id: i64,
+    pub note_id: i64,
+    pub sentence: String,
+    /// Character offset of the chunk in the note content
+    pub start_offset: Option<i64>,
+    pub distance: f64,
+}
+
+/// A stored chunk of a note along with its embedding
+#[derive(Debug, Clone)]
+pub struct NoteChunkWithEmbedding {
+    pub id: i64,
+    pub sentence: String,
+    /// Character offsets of the chunk in the note content
+    pub start_offset: Option<i64>,
+    pub end_offset: Option<i64>,
+    pub embedding: Vec<f32>,
+}
+
 #[derive(Debug)]
 struct NoteChunkToInsert {
     sentence: String,
     sentence_embedding: String,
     sentence_embedding_vector: Vec<f32>,
+    language: Option<String>,
+    start_offset: Option<i64>,
+    end_offset: Option<i64>,
+}
+
+// Character offsets of each chunk in the note content. Chunks overlap, so
+// every search starts at the previous chunk. None when the splitter changed
+// the chunk text and it can't be found verbatim.
+fn locate_chunks_in_content(content: &str, chunks: &[String]) -> Vec<Option<(i64, i64)>> {
+    let mut cursor = 0;
+
+    chunks
+        .iter()
+        .map(|chunk| {
+            let start = cursor + content[cursor..].find(chunk.as_str())?;
+            cursor = start;
+
+            let start_offset = content[..start].chars().count() as i64;
+            let end_offset = start_offset + chunk.chars().count() as i64;
+            Some((start_offset, end_offset))
+        })
+        .collect()
 }
 
 async fn split_markdown_content(content: &str) -> Vec<String> {
@@ -92,7 +209,7 @@ async fn split_markdown_content(content: &str) -> Vec<String> {
         .unwrap()
 }
 
-async fn create_sentence_embedding(text: &str, sentence_encoder: &SentenceEncoder) -> Vec<f32> {
+pub async fn create_sentence_embedding(text: &str, sentence_encoder: &SentenceEncoder) -> Vec<f32> {
     let sentences = [text.to_string()];
 
     let output = sentence_encoder.encode(sentences.to_vec()).await.unwrap();
@@ -100,45 +217,287 @@ async fn create_sentence_embedding(text: &str, sentence_encoder: &SentenceEncode
     output[0].clone()
 }
 
-fn sentence_embedding_to_json(sentence_embedding: &Vec<f32>) -> String {
+pub fn sentence_embedding_to_json(sentence_embedding: &[f32]) -> String {
     serde_json::to_string(sentence_embedding).unwrap()
 }
 
-fn compute_centroid_from_note_content_chunks(chunks: &Vec<NoteChunkToInsert>) -> Option<Vec<f32>> {
-    // Check if the input is empty
-    if chunks.is_empty() {
-        return None;
+/// How chunks contribute to the centroids of a note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CentroidWeighting {
+    /// Every chunk counts the same
+    Uniform,
+    /// Chunks count proportionally to their number of characters, so a one
+    /// line chunk weighs less than a long paragraph
+    ChunkLength,
+}
+
+impl Default for CentroidWeighting {
+    fn default() -> Self {
+        CentroidWeighting::Uniform
     }
+}
 
-    // Get the number of vectors and the dimensionality
-    let num_vectors = chunks.len();
-    let dimension = chunks[0].sentence_embedding_vector.len();
+impl FromStr for CentroidWeighting {
+    type Err = String;
 
-    // Check if all vectors have the same dimension
-    if !chunks
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(CentroidWeighting::Uniform),
+            "chunk_length" => Ok(CentroidWeighting::ChunkLength),
+            _ => Err(format!("Unknown centroid weighting {}", s)),
+        }
+    }
+}
+
+fn chunk_weights(chunks: &[NoteChunkToInsert], weighting: CentroidWeighting) -> Vec<f32> {
+    chunks
         .iter()
-        .all(|chunk| chunk.sentence_embedding_vector.len() == dimension)
-    {
-        return None; // Return None if dimensions mismatch
+        .map(|chunk| match weighting {
+            CentroidWeighting::Uniform => 1.0,
+            CentroidWeighting::ChunkLength => chunk.sentence.chars().count().max(1) as f32,
+        })
+        .collect()
+}
+
+fn compute_centroid_from_note_content_chunks(
+    chunks: &[NoteChunkToInsert],
+    weighting: CentroidWeighting,
+) -> Option<Vec<f32>> {
+    let vectors: Vec<&Vec<f32>> = chunks
+        .iter()
+        .map(|chunk| &chunk.sentence_embedding_vector)
+        .collect();
+
+    weighted_centroid(&vectors, &chunk_weights(chunks, weighting))
+}
+
+// Per-note k-means over the chunks, so a note covering several topics gets a
+// centroid for each of them instead of one in between
+fn compute_centroids_from_note_content_chunks(
+    chunks: &[NoteChunkToInsert],
+    weighting: CentroidWeighting,
+    k: usize,
+) -> Vec<(Vec<f32>, f32)> {
+    let vectors: Vec<&Vec<f32>> = chunks
+        .iter()
+        .map(|chunk| &chunk.sentence_embedding_vector)
+        .collect();
+
+    kmeans(
+        &vectors,
+        &chunk_weights(chunks, weighting),
+        k,
+        KMEANS_MAX_ITERATIONS,
+    )
+}
+
+struct NoteCentroids {
+    average: Vec<f32>,
+    centroids: Vec<(Vec<f32>, f32)>,
+}
+
+async fn compute_note_centroids(
+    state: &tauri::State<'_, AppState>,
+    chunks: &[NoteChunkToInsert],
+) -> Option<NoteCentroids> {
+    let db = &state.db;
+    let weighting = read_setting_or(db, CENTROID_WEIGHTING, CentroidWeighting::default()).await;
+    let k = read_setting_or(db, NOTE_CENTROIDS_K, 1usize).await;
+
+    let average = compute_centroid_from_note_content_chunks(chunks, weighting)?;
+    let centroids = if k > 1 {
+        compute_centroids_from_note_content_chunks(chunks, weighting, k)
+    } else {
+        vec![(average.clone(), 1.0)]
+    };
+
+    Some(NoteCentroids { average, centroids })
+}
+
+async fn replace_note_centroids(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+    centroids: &[(Vec<f32>, f32)],
+) -> Result<(), String> {
+    let db = &state.db;
+
+    sqlx::query("DELETE FROM note_centroids WHERE note_id = ?1")
+        .bind(note_id)
+        .execute(db)
+        .await
+        .map_err(|e| format!("could not delete note centroids {}", e))?;
+
+    if centroids.is_empty() {
+        return Ok(());
     }
 
-    // Initialize a vector to store the sum of each dimension
-    let mut sum_vector = vec![0.0; dimension];
+    let mut insert_note_centroids_query_builder =
+        sqlx::QueryBuilder::new("INSERT INTO note_centroids (centroid, weight, note_id)");
 
-    // Sum each dimension across all vectors
-    for chunk in chunks {
-        for i in 0..dimension {
-            sum_vector[i] += chunk.sentence_embedding_vector[i];
-        }
+    insert_note_centroids_query_builder.push_values(centroids, |mut b, (centroid, weight)| {
+        b.push_bind(sentence_embedding_to_json(centroid))
+            .push_bind(*weight)
+            .push_bind(note_id);
+    });
+
+    insert_note_centroids_query_builder
+        .build()
+        .execute(db)
+        .await
+        .map_err(|e| format!("could not insert note centroids {}", e))?;
+
+    Ok(())
+}
+
+pub async fn get_note_centroids(db: &Db, note_id: i64) -> Result<Vec<Vec<f32>>, String> {
+    let mut blobs: Vec<(Vec<u8>,)> =
+        sqlx::query_as("SELECT centroid FROM note_centroids WHERE note_id = ?1")
+            .bind(note_id)
+            .fetch_all(db)
+            .await
+            .map_err(|e| format!("Failed to get note centroids {}", e))?;
+
+    // Notes indexed before centroids were stored only have their average
+    if blobs.is_empty() {
+        blobs = sqlx::query_as(
+            "SELECT average_sentence_embedding FROM notes WHERE id = ?1 AND average_sentence_embedding IS NOT NULL",
+        )
+        .bind(note_id)
+        .fetch_all(db)
+        .await
+        .map_err(|e| format!("Failed to get note {}", e))?;
     }
 
-    // Compute the average for each dimension to get the centroid
-    let centroid: Vec<f32> = sum_vector
+    Ok(blobs
+        .into_iter()
+        .map(|(blob,)| convert_blob_to_vec_f32(blob).unwrap())
+        .collect())
+}
+
+/// Find the chunks closest to an embedding, nearest first
+pub async fn search_note_chunks(
+    db: &Db,
+    embedding: &[f32],
+    limit: i64,
+) -> Result<Vec<NoteChunkMatch>, String> {
+    let chunks: Vec<NoteChunkMatch> = sqlx::query_as::<_, NoteChunkMatch>(
+        r#"
+        WITH matches AS (
+            SELECT
+                rowid,
+                distance
+            FROM vec_note_chunks
+            WHERE sentence_embedding MATCH ?1
+            ORDER BY distance
+            LIMIT ?2
+        )
+        SELECT
+            note_chunks.id,
+            note_chunks.note_id,
+            note_chunks.sentence,
+            note_chunks.start_offset,
+            matches.distance
+        FROM matches
+        JOIN note_chunks ON note_chunks.id = matches.rowid
+        JOIN notes ON notes.id = note_chunks.note_id
+        WHERE notes.deleted_at IS NULL
+        ORDER BY matches.distance
+        "#,
+    )
+    .bind(sentence_embedding_to_json(embedding))
+    .bind(limit)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to search note chunks {}", e))?;
+
+    Ok(chunks)
+}
+
+/// Chunks of a note in the order they occur
+pub async fn get_note_chunks_with_embeddings(
+    db: &Db,
+    note_id: i64,
+) -> Result<Vec<NoteChunkWithEmbedding>, String> {
+    let rows: Vec<(i64, String, Option<i64>, Option<i64>, Vec<u8>)> = sqlx::query_as(
+        "
+        SELECT id, sentence, start_offset, end_offset, sentence_embedding
+        FROM note_chunks
+        WHERE note_id = ?1 AND sentence_embedding IS NOT NULL
+        ORDER BY id
+        ",
+    )
+    .bind(note_id)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get note chunks {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(id, sentence, start_offset, end_offset, blob)| NoteChunkWithEmbedding {
+                id,
+                sentence,
+                start_offset,
+                end_offset,
+                embedding: convert_blob_to_vec_f32(blob).unwrap(),
+            },
+        )
+        .collect())
+}
+
+/// Average sentence embedding of every note, by note id
+pub async fn get_note_embeddings(db: &Db) -> Result<Vec<(i64, Vec<f32>)>, String> {
+    let rows: Vec<(i64, Vec<u8>)> = sqlx::query_as(
+        "
+        SELECT id, average_sentence_embedding
+        FROM notes
+        WHERE average_sentence_embedding IS NOT NULL AND deleted_at IS NULL
+        ",
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get note embeddings {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, blob)| (id, convert_blob_to_vec_f32(blob).unwrap()))
+        .collect())
+}
+
+/// Fetch notes (without tags) in the order of `ids`
+pub async fn fetch_notes_by_ids(db: &Db, ids: &[i64]) -> Result<Vec<Note>, String> {
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders: Vec<String> = ids
         .iter()
-        .map(|&sum| sum / num_vectors as f32)
+        .enumerate()
+        .map(|(i, _)| format!("?{}", i + 1))
         .collect();
 
-    Some(centroid)
+    let query_str = format!(
+        "
+        SELECT id, content, title, summary, title_is_custom, average_sentence_embedding, language, pinned, archived, favourite, notebook_id, created_at, updated_at
+        FROM notes
+        WHERE id IN ({}) AND deleted_at IS NULL
+        ",
+        placeholders.join(", ")
+    );
+
+    let mut query = sqlx::query_as::<_, Note>(&query_str);
+    for id in ids {
+        query = query.bind(id);
+    }
+
+    let notes: Vec<Note> = query
+        .fetch_all(db)
+        .await
+        .map_err(|e| format!("Failed to get notes {}", e))?;
+
+    let mut notes_map: HashMap<i64, Note> = notes.into_iter().map(|note| (note.id, note)).collect();
+
+    Ok(ids.iter().filter_map(|id| notes_map.remove(id)).collect())
 }
 
 async fn note_splitted_content_to_sentence_embeddings(
@@ -162,6 +521,7 @@ async fn note_content_to_chunks(
     let splitted_content = split_markdown_content(content).await;
     let sentence_embeddings =
         note_splitted_content_to_sentence_embeddings(&state, &splitted_content).await;
+    let offsets = locate_chunks_in_content(content, &splitted_content);
 
     sentence_embeddings
         .iter()
@@ -170,6 +530,9 @@ async fn note_content_to_chunks(
             sentence: splitted_content[i].clone(),
             sentence_embedding: sentence_embedding_to_json(embedding),
             sentence_embedding_vector: embedding.to_vec(),
+            language: detect_language(&splitted_content[i]),
+            start_offset: offsets[i].map(|(start, _)| start),
+            end_offset: offsets[i].map(|(_, end)| end),
         })
         .collect()
 }
@@ -181,47 +544,66 @@ async fn find_similar_words(
     let db = &state.db;
     let word_embeddings_db = &state.word_embeddings_db;
 
-    let note: Vec<Note> = sqlx::query_as::<_, Note>(
-        r#"
-            SELECT * FROM notes
-            WHERE id = ?1
-        "#,
+    // vec_words holds all-MiniLM-L12-v2 vectors, which mean nothing next to
+    // notes embedded by another backend
+    let indexed = read_setting_or(
+        db,
+        INDEXED_EMBEDDING_BACKEND,
+        SentenceEncoderBackend::default(),
     )
-    .bind(note_id)
-    .fetch(db)
-    .try_collect()
-    .await
-    .map_err(|e| format!("Failed to search notes {}", e))?;
-
-    let note_average_content_embedding = &note[0].average_sentence_embedding;
+    .await;
+    if indexed != SentenceEncoderBackend::AllMiniLmL12V2 {
+        return Ok(vec![]);
+    }
 
-    let words: Vec<Word> = sqlx::query_as::<_, Word>(
-        r#"
-        WITH matches AS (
+    let note_centroids = get_note_centroids(db, note_id).await?;
+
+    // Every word keeps its distance to the best matching centroid
+    let mut best_matches: HashMap<i64, Word> = HashMap::new();
+
+    for centroid in &note_centroids {
+        let words: Vec<Word> = sqlx::query_as::<_, Word>(
+            r#"
+            WITH matches AS (
+                SELECT
+                    rowid,
+                    distance
+                FROM 
+                    vec_words
+                WHERE 
+                    sentence_embedding MATCH ?1
+                    AND distance > 0.8
+                ORDER BY 
+                    distance
+                LIMIT 10
+            )
             SELECT
-                rowid,
-                distance
-            FROM 
-                vec_words
-            WHERE 
-                sentence_embedding MATCH ?1
-                AND distance > 0.8
-            ORDER BY 
-                distance
-            LIMIT 10
+                words.id,
+                words.text,
+                matches.distance
+            FROM matches
+            LEFT JOIN words ON words.id = matches.rowid;
+            "#,
         )
-        SELECT
-            words.id,
-            words.text
-        FROM matches
-        LEFT JOIN words ON words.id = matches.rowid;
-        "#,
-    )
-    .bind(serde_json::to_string(note_average_content_embedding).unwrap())
-    .fetch(word_embeddings_db)
-    .try_collect()
-    .await
-    .map_err(|e| format!("Failed to search words {}", e))?;
+        .bind(serde_json::to_string(centroid).unwrap())
+        .fetch(word_embeddings_db)
+        .try_collect()
+        .await
+        .map_err(|e| format!("Failed to search words {}", e))?;
+
+        for word in words {
+            let is_better_match = !best_matches
+                .get(&word.id)
+                .is_some_and(|best| best.distance <= word.distance);
+            if is_better_match {
+                best_matches.insert(word.id, word);
+            }
+        }
+    }
+
+    let mut words: Vec<Word> = best_matches.into_values().collect();
+    words.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    words.truncate(10);
 
     Ok(words.into_iter().map(|word| word.text).collect())
 }
@@ -243,62 +625,77 @@ pub async fn find_similar_notes(
 ) -> Result<Vec<Note>, String> {
     let db = &state.db;
 
-    let notes: Vec<Note> = sqlx::query_as::<_, Note>(
-        r#"
-        WITH avg_embedding AS (
-            SELECT 
-                average_sentence_embedding
-            FROM 
-                notes
-            WHERE 
-                id = ?1
-        ), matches AS (
+    let note_centroids = get_note_centroids(db, note_id).await?;
+
+    // Every note keeps its distance to the best matching centroid
+    let mut best_matches: HashMap<i64, f64> = HashMap::new();
+
+    for centroid in &note_centroids {
+        let matches: Vec<NoteMatch> = sqlx::query_as::<_, NoteMatch>(
+            r#"
+            WITH matches AS (
+                SELECT
+                    rowid,
+                    distance
+                FROM 
+                    vec_note_chunks
+                WHERE 
+                    sentence_embedding MATCH ?1
+                    AND distance > 0.8
+                ORDER BY 
+                    distance
+                LIMIT 10
+            )
             SELECT
-                rowid,
-                distance
-            FROM 
-                vec_note_chunks
-            WHERE 
-                sentence_embedding MATCH (SELECT average_sentence_embedding FROM avg_embedding)
-                AND distance > 0.8
-            ORDER BY 
-                distance
-            LIMIT 10
+                note_chunks.note_id,
+                MIN(matches.distance) AS distance
+            FROM matches
+            JOIN note_chunks ON note_chunks.id = matches.rowid
+            JOIN notes ON notes.id = note_chunks.note_id
+            WHERE note_chunks.note_id != ?2 AND notes.deleted_at IS NULL
+            GROUP BY note_chunks.note_id;
+            "#,
         )
-        SELECT DISTINCT
-            notes.id,
-            notes.content,
-            notes.average_sentence_embedding,
-            notes.created_at,
-            notes.updated_at
-        FROM matches
-        JOIN note_chunks ON note_chunks.id = matches.rowid
-        LEFT JOIN notes ON notes.id = note_chunks.note_id;
-        "#,
-    )
-    .bind(note_id)
-    .fetch(db)
-    .try_collect()
-    .await
-    .map_err(|e| format!("Failed to search notes {}", e))?;
+        .bind(sentence_embedding_to_json(centroid))
+        .bind(note_id)
+        .fetch(db)
+        .try_collect()
+        .await
+        .map_err(|e| format!("Failed to search notes {}", e))?;
 
-    Ok(notes)
+        for note_match in matches {
+            let best = best_matches
+                .entry(note_match.note_id)
+                .or_insert(note_match.distance);
+            *best = best.min(note_match.distance);
+        }
+    }
+
+    let mut matches: Vec<(i64, f64)> = best_matches.into_iter().collect();
+    matches.sort_by(|a, b| a.1.total_cmp(&b.1));
+    matches.truncate(10);
+
+    let note_ids: Vec<i64> = matches.into_iter().map(|(id, _)| id).collect();
+
+    fetch_notes_by_ids(db, &note_ids).await
 }
 
-#[tauri::command]
-pub async fn search_notes(
-    state: tauri::State<'_, AppState>,
-    query: String,
+/// Notes with the chunks closest to an embedding, nearest first. The KNN
+/// step can't filter, so it is given more chunks than notes are returned and
+/// retried with more until enough notes pass the filters or every chunk was
+/// considered. Otherwise a small notebook would rarely make the first cut.
+async fn search_notes_by_embedding(
+    db: &Db,
+    embedding: &[f32],
+    include_archived: bool,
+    notebook_id: Option<i64>,
 ) -> Result<Vec<Note>, String> {
-    let start = Instant::now();
-
-    let db = &state.db;
-    let sentence_encoder = &state.sentence_encoder;
-    let sentences = [query.clone()];
-    let output = sentence_encoder.encode(sentences.to_vec()).await.unwrap();
-    let embedding_json = serde_json::to_string(&output[0]).unwrap();
+    let (chunk_count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM note_chunks")
+        .fetch_one(db)
+        .await
+        .map_err(|e| format!("Failed to count note chunks {}", e))?;
 
-    let notes: Vec<Note> = sqlx::query_as::<_, Note>(
+    let query = format!(
         r#"
     WITH matches AS (
         SELECT
@@ -308,32 +705,83 @@ pub async fn search_notes(
         WHERE sentence_embedding MATCH (?1)
             AND distance > 0.8
         ORDER BY distance
-        LIMIT 10
+        LIMIT ?4
     )
-    SELECT DISTINCT
+    SELECT
         notes.id,
         notes.content,
+        notes.title,
+        notes.summary,
+        notes.title_is_custom,
         notes.average_sentence_embedding,
+        notes.language,
+        notes.pinned,
+        notes.archived,
+        notes.favourite,
+        notes.notebook_id,
         notes.created_at,
         notes.updated_at
     FROM matches
     JOIN note_chunks ON note_chunks.id = matches.rowid
-    LEFT JOIN notes ON notes.id = note_chunks.note_id"#,
-    )
-    .bind(embedding_json)
-    .fetch(db)
-    .try_collect()
-    .await
-    .map_err(|e| format!("Failed to search notes {}", e))?;
+    JOIN notes ON notes.id = note_chunks.note_id
+    WHERE notes.deleted_at IS NULL
+        AND (?2 OR notes.archived = 0)
+        AND (?3 IS NULL OR notes.notebook_id IN ({}))
+    GROUP BY notes.id
+    ORDER BY MIN(matches.distance)
+    LIMIT ?5"#,
+        notebook_subtree_query("?3")
+    );
 
-    let duration = start.elapsed();
-    println!("Time elapsed in search_notes() is: {:?}", duration);
+    let mut candidates = SEARCH_RESULTS * 5;
+
+    loop {
+        let notes: Vec<Note> = sqlx::query_as::<_, Note>(&query)
+            .bind(sentence_embedding_to_json(embedding))
+            .bind(include_archived)
+            .bind(notebook_id)
+            .bind(candidates)
+            .bind(SEARCH_RESULTS)
+            .fetch(db)
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to search notes {}", e))?;
+
+        if notes.len() as i64 >= SEARCH_RESULTS || candidates >= chunk_count {
+            return Ok(notes);
+        }
+
+        candidates *= 4;
+    }
+}
+
+#[tauri::command]
+pub async fn search_notes(
+    state: tauri::State<'_, AppState>,
+    query: String,
+    include_archived: Option<bool>,
+    // Notes of this notebook and its sub-notebooks
+    notebook_id: Option<i64>,
+) -> Result<Vec<Note>, String> {
+    let db = &state.db;
+    let sentence_encoder = &state.sentence_encoder;
+    let sentences = [query.clone()];
+    let output = sentence_encoder.encode(sentences.to_vec()).await.unwrap();
+
+    // Archived notes stay searchable unless asked otherwise
+    let notes = search_notes_by_embedding(
+        db,
+        &output[0],
+        include_archived.unwrap_or(true),
+        notebook_id,
+    )
+    .await?;
 
     Ok(notes)
 }
 
 // Delete note_chunks and vec_note_chunks
-async fn delete_note_vector_embeddings(
+pub async fn delete_note_vector_embeddings(
     state: &tauri::State<'_, AppState>,
     note_id: i64,
 ) -> Result<(), String> {
@@ -367,12 +815,16 @@ async fn insert_note_vector_embeddings(
 ) -> Result<(), String> {
     let db = &state.db;
 
-    let mut insert_note_chunks_query_builder =
-        sqlx::QueryBuilder::new("INSERT INTO note_chunks (sentence, sentence_embedding, note_id)");
+    let mut insert_note_chunks_query_builder = sqlx::QueryBuilder::new(
+        "INSERT INTO note_chunks (sentence, sentence_embedding, language, start_offset, end_offset, note_id)",
+    );
 
     insert_note_chunks_query_builder.push_values(note_content_chunks, |mut b, chunk| {
         b.push_bind(&chunk.sentence)
             .push_bind(&chunk.sentence_embedding)
+            .push_bind(&chunk.language)
+            .push_bind(chunk.start_offset)
+            .push_bind(chunk.end_offset)
             .push_bind(note_id);
     });
 
@@ -380,12 +832,14 @@ async fn insert_note_vector_embeddings(
 
     insert_note_chunks_query.execute(db).await.unwrap();
 
+    // Trashed notes keep their chunks out of the KNN index until restored
     sqlx::query(
         r#"
     WITH related_note_chunks AS (
-        SELECT rowid, sentence_embedding
+        SELECT note_chunks.rowid, note_chunks.sentence_embedding
         FROM note_chunks
-        WHERE "note_chunks"."note_id" = ?1
+        JOIN notes ON notes.id = note_chunks.note_id
+        WHERE "note_chunks"."note_id" = ?1 AND notes.deleted_at IS NULL
     )
     INSERT INTO vec_note_chunks (rowid, sentence_embedding)
     SELECT rowid, sentence_embedding
@@ -399,43 +853,198 @@ async fn insert_note_vector_embeddings(
     Ok(())
 }
 
+// Replace the chunks, centroid and detected language of a note with ones
+// computed from `content` by the running sentence encoder
+pub async fn reindex_note(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+    content: &str,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    delete_note_vector_embeddings(state, note_id).await?;
+
+    let note_content_chunks = note_content_to_chunks(state, content).await;
+
+    insert_note_vector_embeddings(state, note_id, &note_content_chunks).await?;
+
+    let note_centroids = compute_note_centroids(state, &note_content_chunks)
+        .await
+        .ok_or(format!("could not compute embedding of note {}", note_id))?;
+
+    replace_note_centroids(state, note_id, &note_centroids.centroids).await?;
+
+    sqlx::query("UPDATE notes SET average_sentence_embedding = ?1, language = ?2 WHERE id = ?3")
+        .bind(sentence_embedding_to_json(&note_centroids.average))
+        .bind(detect_language(content))
+        .bind(note_id)
+        .execute(db)
+        .await
+        .map_err(|e| format!("could not update note {}", e))?;
+
+    Ok(())
+}
+
+/// Re-embed every note with the running sentence encoder. Embeddings from
+/// different backends live in different spaces, so this has to run after the
+/// backend changes before search results make sense again.
+pub async fn reindex_all_notes(state: &tauri::State<'_, AppState>) -> Result<(), String> {
+    let db = &state.db;
+
+    let notes: Vec<(i64, String)> = sqlx::query_as("SELECT id, content FROM notes")
+        .fetch_all(db)
+        .await
+        .map_err(|e| format!("Failed to get notes {}", e))?;
+
+    for (id, content) in notes {
+        reindex_note(state, id, &content).await?;
+    }
+
+    write_setting(
+        db,
+        INDEXED_EMBEDDING_BACKEND,
+        state.sentence_encoder_backend.as_str(),
+    )
+    .await?;
+    // reindex_note detected the language of every note and chunk
+    write_setting(db, LANGUAGES_BACKFILLED, "true").await?;
+
+    Ok(())
+}
+
+/// Detect the language of the notes and chunks saved before language
+/// detection existed, once. Notes reindexed since then already have theirs.
+pub async fn backfill_note_languages(db: &Db) -> Result<(), String> {
+    if read_setting_or(db, LANGUAGES_BACKFILLED, false).await {
+        return Ok(());
+    }
+
+    let notes: Vec<(i64, String)> =
+        sqlx::query_as("SELECT id, content FROM notes WHERE language IS NULL")
+            .fetch_all(db)
+            .await
+            .map_err(|e| format!("Failed to get notes {}", e))?;
+
+    for (id, content) in notes {
+        sqlx::query("UPDATE notes SET language = ?1 WHERE id = ?2")
+            .bind(detect_language(&content))
+            .bind(id)
+            .execute(db)
+            .await
+            .map_err(|e| format!("could not update note {}", e))?;
+    }
+
+    let chunks: Vec<(i64, String)> =
+        sqlx::query_as("SELECT id, sentence FROM note_chunks WHERE language IS NULL")
+            .fetch_all(db)
+            .await
+            .map_err(|e| format!("Failed to get note chunks {}", e))?;
+
+    for (id, sentence) in chunks {
+        sqlx::query("UPDATE note_chunks SET language = ?1 WHERE id = ?2")
+            .bind(detect_language(&sentence))
+            .bind(id)
+            .execute(db)
+            .await
+            .map_err(|e| format!("could not update note chunk {}", e))?;
+    }
+
+    write_setting(db, LANGUAGES_BACKFILLED, "true").await
+}
+
+/// Whether the stored embeddings were created by another backend than the
+/// running sentence encoder
+pub async fn needs_reindex(state: &tauri::State<'_, AppState>) -> bool {
+    let indexed = read_setting_or(
+        &state.db,
+        INDEXED_EMBEDDING_BACKEND,
+        SentenceEncoderBackend::default(),
+    )
+    .await;
+
+    indexed != state.sentence_encoder_backend
+}
+
+#[tauri::command]
+pub async fn reindex_notes(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    reindex_all_notes(&state).await?;
+
+    let _ = app_handle.emit_all("refetch_notes", "");
+
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn create_note(
     app_handle: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
     content: String,
+    notebook_id: Option<i64>,
 ) -> Result<i64, String> {
+    // notebook_id has no foreign key
+    let notebook_id = notebook_id.unwrap_or(DEFAULT_NOTEBOOK_ID);
+    ensure_notebook_exists(&state.db, notebook_id).await?;
+
     let note_content_chunks = note_content_to_chunks(&state, &content).await;
 
-    let average_sentence_embedding =
-        compute_centroid_from_note_content_chunks(&note_content_chunks).unwrap();
+    let note_centroids = compute_note_centroids(&state, &note_content_chunks)
+        .await
+        .unwrap();
 
     let db = &state.db;
 
     // notes
-    let inserted_note =
-        sqlx::query("INSERT INTO notes (content, average_sentence_embedding) VALUES (?1, ?2)")
-            .bind(content.clone())
-            .bind(sentence_embedding_to_json(&average_sentence_embedding))
-            .execute(db)
-            .await
-            .unwrap();
+    let inserted_note = sqlx::query(
+        "INSERT INTO notes (content, average_sentence_embedding, language, notebook_id) VALUES (?1, ?2, ?3, ?4)",
+    )
+    .bind(content.clone())
+    .bind(sentence_embedding_to_json(&note_centroids.average))
+    .bind(detect_language(&content))
+    .bind(notebook_id)
+    .execute(db)
+    .await
+    .unwrap();
 
     let inserted_note_row_id = inserted_note.last_insert_rowid();
 
     let _ = insert_note_vector_embeddings(&state, inserted_note_row_id, &note_content_chunks).await;
+    let _ = replace_note_centroids(&state, inserted_note_row_id, &note_centroids.centroids).await;
+    // The note is saved already, its links can be parsed again later
+    if let Err(err) = update_note_links(db, inserted_note_row_id, &content).await {
+        log::warn!(
+            "could not save links of note {} {}",
+            inserted_note_row_id,
+            err
+        );
+    }
 
     let _ = app_handle.emit_all("refetch_notes", "");
 
+    spawn_note_enrichment(app_handle, inserted_note_row_id);
+
     Ok(inserted_note_row_id)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchParams {
-    tag_ids: Vec<String>,
+    /// Tags below a given tag match as well
+    tag_ids: Vec<i64>,
     match_all: bool,
     take: Option<i64>,
     skip: Option<i64>,
+    /// Only pinned (true) or unpinned (false) notes, both when missing
+    pinned: Option<bool>,
+    /// Only archived (true) or unarchived (false) notes, unarchived when missing
+    archived: Option<bool>,
+    /// Only favourite (true) or other (false) notes, both when missing
+    favourite: Option<bool>,
+    /// Only notes of this notebook
+    notebook_id: Option<i64>,
+    /// Whether notes of sub-notebooks count as in the notebook, defaults to true
+    include_sub_notebooks: Option<bool>,
 }
 
 #[tauri::command]
@@ -444,61 +1053,53 @@ pub async fn get_notes(
     params: SearchParams,
 ) -> Result<Vec<Note>, String> {
     let db = &state.db;
-    let tag_ids_count = params.tag_ids.len();
-
-    // Create placeholders for the query
-    let placeholders: Vec<String> = params
-        .tag_ids
-        .iter()
-        .enumerate()
-        .map(|(i, _)| format!("?{}", i + 1))
-        .collect();
-    let placeholders_str = placeholders.join(", ");
 
-    // Build the query dynamically based on match_all flag
-    let base_query_str = if params.tag_ids.len() == 0 {
-        format!(
-            "
-            SELECT n.id, n.content, n.average_sentence_embedding, n.created_at, n.updated_at, t.id as tag_id
-            FROM notes n
-            LEFT JOIN notes_to_tags nt ON n.id = nt.note_id
-            LEFT JOIN tags t ON nt.tag_id = t.id
+    let mut base_query_str = format!(
         "
-        )
-    } else if params.match_all {
-        format!(
-            "WITH TagNotes AS (
-                SELECT nt.note_id
-                FROM notes_to_tags nt
-                JOIN tags t ON nt.tag_id = t.id
-                WHERE t.id IN ({})
-                GROUP BY nt.note_id
-                HAVING COUNT(DISTINCT t.id) = {}
-            )
-            SELECT n.id, n.content, n.average_sentence_embedding, n.created_at, n.updated_at, t.id as tag_id
-            FROM notes n
-            JOIN TagNotes tn ON n.id = tn.note_id
-            LEFT JOIN notes_to_tags nt ON n.id = nt.note_id
-            LEFT JOIN tags t ON nt.tag_id = t.id",
-            placeholders_str, tag_ids_count
-        )
-    } else {
-        format!(
-            "SELECT DISTINCT n.id, n.content, n.average_sentence_embedding, n.created_at, n.updated_at, t.id as tag_id
+            SELECT {}
             FROM notes n
             LEFT JOIN notes_to_tags nt ON n.id = nt.note_id
             LEFT JOIN tags t ON nt.tag_id = t.id
-            WHERE nt.tag_id IN ({})",
-            placeholders_str
-        )
-    };
+            LEFT JOIN tag_paths tp ON tp.id = t.id
+            WHERE n.deleted_at IS NULL
+        ",
+        NOTE_WITH_TAG_COLUMNS
+    );
+
+    // A tag matches notes with the tag or any tag below it
+    if let Some(condition) = tag_filter_condition("n.id", params.tag_ids.len(), 1, params.match_all)
+    {
+        base_query_str.push_str(&format!(" AND {}", condition));
+    }
 
     // Apply take and skip parameters
     let mut final_query_str = base_query_str.clone();
 
+    // Every base query ends with a WHERE clause
+    if let Some(pinned) = params.pinned {
+        final_query_str.push_str(&format!(" AND n.pinned = {}", pinned as i64));
+    }
+    final_query_str.push_str(&format!(
+        " AND n.archived = {}",
+        params.archived.unwrap_or(false) as i64
+    ));
+    if let Some(favourite) = params.favourite {
+        final_query_str.push_str(&format!(" AND n.favourite = {}", favourite as i64));
+    }
+    if let Some(notebook_id) = params.notebook_id {
+        if params.include_sub_notebooks.unwrap_or(true) {
+            final_query_str.push_str(&format!(
+                " AND n.notebook_id IN ({})",
+                notebook_subtree_query(&notebook_id.to_string())
+            ));
+        } else {
+            final_query_str.push_str(&format!(" AND n.notebook_id = {}", notebook_id));
+        }
+    }
+
     final_query_str.push_str(
         "
-    ORDER BY n.updated_at DESC
+    ORDER BY n.pinned DESC, n.updated_at DESC
     ",
     );
 
@@ -530,20 +1131,28 @@ pub async fn get_notes(
         let entry = notes_map.entry(row.id).or_insert(Note {
             id: row.id,
             content: row.content.clone(),
+            title: row.title.clone(),
+            summary: row.summary.clone(),
+            title_is_custom: row.title_is_custom,
             average_sentence_embedding,
+            language: row.language.clone(),
+            pinned: row.pinned,
+            archived: row.archived,
+            favourite: row.favourite,
+            notebook_id: row.notebook_id,
             created_at: row.created_at,
             updated_at: row.updated_at,
             tags: vec![],
         });
 
-        if let Some(tag_id) = row.tag_id {
-            entry.tags.push(Tag { id: tag_id });
+        if let Some(tag) = row.tag() {
+            entry.tags.push(tag);
         }
     }
 
-    // Convert HashMap to Vec and sort by updated_at
+    // Convert HashMap to Vec and sort by updated_at, pinned notes first
     let mut notes: Vec<Note> = notes_map.into_values().collect();
-    notes.sort_by_key(|note| std::cmp::Reverse(note.updated_at));
+    notes.sort_by_key(|note| std::cmp::Reverse((note.pinned, note.updated_at)));
 
     Ok(notes)
 }
@@ -553,15 +1162,17 @@ pub async fn get_note(state: tauri::State<'_, AppState>, id: i64) -> Result<Note
     let db = &state.db;
 
     // Fetch note along with its tags
-    let rows: Vec<NoteWithTag> = sqlx::query_as::<_, NoteWithTag>(
+    let rows: Vec<NoteWithTag> = sqlx::query_as::<_, NoteWithTag>(&format!(
         "
-        SELECT n.id, n.content, n.average_sentence_embedding, n.created_at, n.updated_at, t.id as tag_id
+        SELECT {}
         FROM notes n
         LEFT JOIN notes_to_tags nt ON n.id = nt.note_id
         LEFT JOIN tags t ON nt.tag_id = t.id
+        LEFT JOIN tag_paths tp ON tp.id = t.id
         WHERE n.id = ?1
         ",
-    )
+        NOTE_WITH_TAG_COLUMNS
+    ))
     .bind(id)
     .fetch_all(db)
     .await
@@ -575,18 +1186,26 @@ pub async fn get_note(state: tauri::State<'_, AppState>, id: i64) -> Result<Note
     let mut note = Note {
         id: rows[0].id,
         content: rows[0].content.clone(),
+        title: rows[0].title.clone(),
+        summary: rows[0].summary.clone(),
+        title_is_custom: rows[0].title_is_custom,
         average_sentence_embedding: convert_blob_to_vec_f32(
             rows[0].average_sentence_embedding.clone(),
         )
         .unwrap(),
+        language: rows[0].language.clone(),
+        pinned: rows[0].pinned,
+        archived: rows[0].archived,
+        favourite: rows[0].favourite,
+        notebook_id: rows[0].notebook_id,
         created_at: rows[0].created_at,
         updated_at: rows[0].updated_at,
         tags: vec![],
     };
 
     for row in rows {
-        if let Some(tag_id) = row.tag_id {
-            note.tags.push(Tag { id: tag_id });
+        if let Some(tag) = row.tag() {
+            note.tags.push(tag);
         }
     }
 
@@ -604,51 +1223,54 @@ pub async fn update_note(
 
     let db = &state.db;
 
-    let _ = delete_note_vector_embeddings(&state, id).await;
-
-    let note_content_chunks = note_content_to_chunks(&state, &content).await;
-
-    let _ = insert_note_vector_embeddings(&state, id, &note_content_chunks).await;
+    snapshot_note_before_update(db, id, &content).await?;
 
-    let average_sentence_embedding =
-        compute_centroid_from_note_content_chunks(&note_content_chunks).unwrap();
+    reindex_note(&state, id, &content).await?;
 
     let new_date = chrono::Utc::now().timestamp();
-    sqlx::query("UPDATE notes SET content = ?1, average_sentence_embedding = ?2, updated_at = ?3 WHERE id = ?4")
-        .bind(content)
-        .bind(sentence_embedding_to_json(&average_sentence_embedding))
+    sqlx::query("UPDATE notes SET content = ?1, updated_at = ?2 WHERE id = ?3")
+        .bind(&content)
         .bind(new_date)
         .bind(id)
         .execute(db)
         .await
         .map_err(|e| format!("could not update note {}", e))?;
 
+    update_note_links(db, id, &content).await?;
+
     println!("finished update_note");
     let duration = start.elapsed();
     println!("Time elapsed in update_note() is: {:?}", duration);
 
     let _ = app_handle.emit_all("refetch_notes", "");
 
+    spawn_note_enrichment(app_handle, id);
+
     Ok(())
 }
 
+/// Move a note to the trash. It keeps its chunks so it can be restored
+/// without reindexing, see `purge_notes` for deleting it for good.
 #[tauri::command]
 pub async fn delete_note(
     app_handle: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
     id: i64,
 ) -> Result<(), String> {
-    let db = &state.db;
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
 
-    let _ = delete_note_vector_embeddings(&state, id.clone());
+    trash_note(&mut tx, id).await?;
 
-    sqlx::query("DELETE FROM notes WHERE id = ?1")
-        .bind(id)
-        .execute(db)
+    tx.commit()
         .await
         .map_err(|e| format!("could not delete note {}", e))?;
 
     let _ = app_handle.emit_all("refetch_notes", "");
+    let _ = app_handle.emit_all("refetch_trash", "");
 
     Ok(())
 }
@@ -658,7 +1280,7 @@ pub async fn delete_note_tag(
     app_handle: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
     note_id: i64,
-    tag_id: String,
+    tag_id: i64,
 ) -> Result<(), String> {
     let db = &state.db;
 
@@ -673,3 +1295,225 @@ pub async fn delete_note_tag(
 
     Ok(())
 }
+
+#[tauri::command]
+pub async fn set_note_pinned(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    pinned: bool,
+) -> Result<(), String> {
+    sqlx::query("UPDATE notes SET pinned = ?1 WHERE id = ?2")
+        .bind(pinned)
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| format!("could not pin note {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_notes", "");
+
+    Ok(())
+}
+
+/// Archived notes are left out of `get_notes` unless asked for, but stay
+/// searchable
+#[tauri::command]
+pub async fn set_note_archived(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    archived: bool,
+) -> Result<(), String> {
+    sqlx::query("UPDATE notes SET archived = ?1 WHERE id = ?2")
+        .bind(archived)
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| format!("could not archive note {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_notes", "");
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_note_favourite(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    favourite: bool,
+) -> Result<(), String> {
+    sqlx::query("UPDATE notes SET favourite = ?1 WHERE id = ?2")
+        .bind(favourite)
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| format!("could not update favourite note {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_notes", "");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup_test_db;
+
+    fn unit_vector(dimension: usize) -> Vec<f32> {
+        let mut vector = vec![0.0; 384];
+        vector[dimension] = 1.0;
+        vector
+    }
+
+    async fn insert_indexed_note(db: &Db, notebook_id: i64, embedding: &[f32]) -> i64 {
+        let embedding = sentence_embedding_to_json(embedding);
+
+        let note_id: i64 = sqlx::query_scalar(
+            "INSERT INTO notes (content, average_sentence_embedding, notebook_id) VALUES ('', ?1, ?2) RETURNING id",
+        )
+        .bind(&embedding)
+        .bind(notebook_id)
+        .fetch_one(db)
+        .await
+        .unwrap();
+
+        let chunk_id: i64 = sqlx::query_scalar(
+            "INSERT INTO note_chunks (sentence, sentence_embedding, note_id) VALUES ('', ?1, ?2) RETURNING id",
+        )
+        .bind(&embedding)
+        .bind(note_id)
+        .fetch_one(db)
+        .await
+        .unwrap();
+
+        sqlx::query("INSERT INTO vec_note_chunks (rowid, sentence_embedding) VALUES (?1, ?2)")
+            .bind(chunk_id)
+            .bind(&embedding)
+            .execute(db)
+            .await
+            .unwrap();
+
+        note_id
+    }
+
+    #[test]
+    fn chunks_are_located_by_character_offsets() {
+        let content = "Héllo wörld. Héllo again.";
+        let chunks: Vec<String> = ["Héllo wörld.", "wörld. Héllo", "missing", "Héllo again."]
+            .iter()
+            .map(|chunk| chunk.to_string())
+            .collect();
+
+        assert_eq!(
+            locate_chunks_in_content(content, &chunks),
+            vec![Some((0, 12)), Some((6, 18)), None, Some((13, 25))]
+        );
+    }
+
+    #[tokio::test]
+    async fn search_finds_notes_of_a_small_notebook() {
+        let db = setup_test_db().await;
+
+        let notebook_id: i64 =
+            sqlx::query_scalar("INSERT INTO notebooks (name) VALUES ('Small') RETURNING id")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+
+        // Plenty of notes outside the notebook are closer to the query
+        for _ in 0..60 {
+            insert_indexed_note(&db, DEFAULT_NOTEBOOK_ID, &unit_vector(1)).await;
+        }
+        let mut far = unit_vector(0);
+        far[0] = -1.0;
+        let note_id = insert_indexed_note(&db, notebook_id, &far).await;
+
+        let notes = search_notes_by_embedding(&db, &unit_vector(0), true, Some(notebook_id))
+            .await
+            .unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].id, note_id);
+
+        let notes = search_notes_by_embedding(&db, &unit_vector(0), true, None)
+            .await
+            .unwrap();
+        assert_eq!(notes.len(), SEARCH_RESULTS as usize);
+        assert!(notes.iter().all(|note| note.id != note_id));
+    }
+
+    #[tokio::test]
+    async fn search_finds_unarchived_notes_past_archived_ones() {
+        let db = setup_test_db().await;
+
+        for _ in 0..60 {
+            let note_id = insert_indexed_note(&db, DEFAULT_NOTEBOOK_ID, &unit_vector(1)).await;
+            sqlx::query("UPDATE notes SET archived = 1 WHERE id = ?1")
+                .bind(note_id)
+                .execute(&db)
+                .await
+                .unwrap();
+        }
+        let mut far = unit_vector(0);
+        far[0] = -1.0;
+        let note_id = insert_indexed_note(&db, DEFAULT_NOTEBOOK_ID, &far).await;
+
+        let notes = search_notes_by_embedding(&db, &unit_vector(0), false, None)
+            .await
+            .unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].id, note_id);
+    }
+
+    #[tokio::test]
+    async fn languages_of_older_notes_are_detected_once() {
+        let db = setup_test_db().await;
+
+        let content = "Every evening I walk along the river with my dog, and we watch the boats come back to the harbour before the sun goes down behind the hills.";
+        let note_id = insert_indexed_note(&db, DEFAULT_NOTEBOOK_ID, &unit_vector(0)).await;
+        sqlx::query("UPDATE notes SET content = ?1 WHERE id = ?2")
+            .bind(content)
+            .bind(note_id)
+            .execute(&db)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE note_chunks SET sentence = ?1 WHERE note_id = ?2")
+            .bind(content)
+            .bind(note_id)
+            .execute(&db)
+            .await
+            .unwrap();
+
+        backfill_note_languages(&db).await.unwrap();
+
+        let language: Option<String> =
+            sqlx::query_scalar("SELECT language FROM notes WHERE id = ?1")
+                .bind(note_id)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(language.as_deref(), Some("eng"));
+        let language: Option<String> =
+            sqlx::query_scalar("SELECT language FROM note_chunks WHERE note_id = ?1")
+                .bind(note_id)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(language.as_deref(), Some("eng"));
+
+        sqlx::query("UPDATE notes SET language = NULL WHERE id = ?1")
+            .bind(note_id)
+            .execute(&db)
+            .await
+            .unwrap();
+        backfill_note_languages(&db).await.unwrap();
+
+        let language: Option<String> =
+            sqlx::query_scalar("SELECT language FROM notes WHERE id = ?1")
+                .bind(note_id)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(language, None);
+    }
+}
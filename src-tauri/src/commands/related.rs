@@ -0,0 +1,60 @@
+use crate::commands::notes::{create_sentence_embedding, search_note_chunks, NoteChunkMatch};
+use crate::AppState;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+// Quiet time after the last keystroke before searching
+const DEBOUNCE: Duration = Duration::from_millis(300);
+// Shorter paragraphs don't carry enough meaning to search with
+const MIN_PARAGRAPH_CHARS: usize = 20;
+
+/// Chunks of other notes closest to the paragraph being edited, nearest
+/// first. Every call supersedes the previous one: a call that is superseded
+/// during the debounce, or while embedding, or that is cancelled with
+/// `cancel_related_while_typing`, returns None.
+#[tauri::command]
+pub async fn find_related_while_typing(
+    state: tauri::State<'_, AppState>,
+    paragraph: String,
+    note_id: Option<i64>,
+    limit: Option<i64>,
+) -> Result<Option<Vec<NoteChunkMatch>>, String> {
+    let generation = state.related_generation.fetch_add(1, Ordering::SeqCst) + 1;
+    let is_current = || state.related_generation.load(Ordering::SeqCst) == generation;
+
+    tokio::time::sleep(DEBOUNCE).await;
+    if !is_current() {
+        return Ok(None);
+    }
+
+    let paragraph = paragraph.trim();
+    if paragraph.chars().count() < MIN_PARAGRAPH_CHARS {
+        return Ok(Some(vec![]));
+    }
+
+    let embedding = create_sentence_embedding(paragraph, &state.sentence_encoder).await;
+    if !is_current() {
+        return Ok(None);
+    }
+
+    let limit = limit.unwrap_or(5);
+    // The note being edited can take some of the closest chunks
+    let mut chunks = search_note_chunks(&state.db, &embedding, limit * 3).await?;
+    if !is_current() {
+        return Ok(None);
+    }
+
+    chunks.retain(|chunk| Some(chunk.note_id) != note_id);
+    chunks.truncate(limit as usize);
+
+    Ok(Some(chunks))
+}
+
+/// Drop the pending `find_related_while_typing` call, e.g. when the editor
+/// closes
+#[tauri::command]
+pub async fn cancel_related_while_typing(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    state.related_generation.fetch_add(1, Ordering::SeqCst);
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use crate::commands::notes::{fetch_notes_by_ids, get_note_centroids, search_note_chunks, Note};
+use crate::commands::settings::{read_setting_or, RESURFACE_STALE_DAYS};
+use crate::AppState;
+use std::collections::{HashMap, HashSet};
+
+const DAY: i64 = 24 * 60 * 60;
+// Notes edited this recently make up the recent activity
+const RECENT_DAYS: i64 = 7;
+const RECENT_NOTES: i64 = 10;
+// Chunks looked up per centroid of a recent note
+const CHUNK_NEIGHBOURS: i64 = 30;
+// Notes picked each day, more than any list shows so that every `limit` is
+// served from the same picks
+const DAILY_PICKS: i64 = 20;
+
+/// Remember that a note was opened
+#[tauri::command]
+pub async fn record_note_view(
+    state: tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<(), String> {
+    sqlx::query(
+        "
+        INSERT INTO note_views (note_id, last_viewed_at, view_count) VALUES (?1, ?2, 1)
+        ON CONFLICT(note_id) DO UPDATE SET
+            last_viewed_at = excluded.last_viewed_at,
+            view_count = view_count + 1
+        ",
+    )
+    .bind(note_id)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db)
+    .await
+    .map_err(|e| format!("could not record note view {}", e))?;
+
+    Ok(())
+}
+
+/// Notes close to what was edited lately but not opened for a long time
+async fn pick_resurface_notes(
+    state: &tauri::State<'_, AppState>,
+    limit: i64,
+) -> Result<Vec<i64>, String> {
+    let db = &state.db;
+    let now = chrono::Utc::now().timestamp();
+    let stale_days = read_setting_or(db, RESURFACE_STALE_DAYS, 30i64).await;
+
+    let recent_notes: Vec<(i64,)> = sqlx::query_as(
+        "SELECT id FROM notes WHERE updated_at >= ?1 ORDER BY updated_at DESC LIMIT ?2",
+    )
+    .bind(now - RECENT_DAYS * DAY)
+    .bind(RECENT_NOTES)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get recent notes {}", e))?;
+    let recent_notes: HashSet<i64> = recent_notes.into_iter().map(|(id,)| id).collect();
+
+    // Notes never opened since views are tracked count from their last edit
+    let stale_notes: Vec<(i64,)> = sqlx::query_as(
+        "
+        SELECT notes.id
+        FROM notes
+        LEFT JOIN note_views ON note_views.note_id = notes.id
+        WHERE COALESCE(note_views.last_viewed_at, notes.updated_at, notes.created_at) < ?1
+        ",
+    )
+    .bind(now - stale_days * DAY)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get stale notes {}", e))?;
+    let stale_notes: HashSet<i64> = stale_notes.into_iter().map(|(id,)| id).collect();
+
+    // Every stale note keeps its distance to the closest recent centroid
+    let mut best_distances: HashMap<i64, f64> = HashMap::new();
+
+    for recent_note_id in &recent_notes {
+        for centroid in get_note_centroids(db, *recent_note_id).await? {
+            for chunk in search_note_chunks(db, &centroid, CHUNK_NEIGHBOURS).await? {
+                if !stale_notes.contains(&chunk.note_id) {
+                    continue;
+                }
+                let is_better_match = !best_distances
+                    .get(&chunk.note_id)
+                    .is_some_and(|best| *best <= chunk.distance);
+                if is_better_match {
+                    best_distances.insert(chunk.note_id, chunk.distance);
+                }
+            }
+        }
+    }
+
+    let mut picks: Vec<(i64, f64)> = best_distances.into_iter().collect();
+    picks.sort_by(|a, b| a.1.total_cmp(&b.1));
+    picks.truncate(limit as usize);
+
+    Ok(picks.into_iter().map(|(note_id, _)| note_id).collect())
+}
+
+/// Today's notes worth another look: semantically close to the recently
+/// edited notes but not opened for `resurface_stale_days`. Picked once a
+/// day, so the list doesn't change while the day goes on. `limit` defaults
+/// to 3 and can't go past `DAILY_PICKS`.
+#[tauri::command]
+pub async fn get_daily_resurface(
+    state: tauri::State<'_, AppState>,
+    limit: Option<i64>,
+) -> Result<Vec<Note>, String> {
+    let db = &state.db;
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+
+    let picked: Vec<(i64,)> =
+        sqlx::query_as("SELECT note_id FROM daily_resurface WHERE day = ?1 ORDER BY rank")
+            .bind(&today)
+            .fetch_all(db)
+            .await
+            .map_err(|e| format!("Failed to get resurfaced notes {}", e))?;
+    let mut note_ids: Vec<i64> = picked.into_iter().map(|(id,)| id).collect();
+
+    if note_ids.is_empty() {
+        note_ids = pick_resurface_notes(&state, DAILY_PICKS).await?;
+
+        let mut tx = db
+            .begin()
+            .await
+            .map_err(|e| format!("could not start transaction {}", e))?;
+
+        // Older picks are of no use anymore
+        sqlx::query("DELETE FROM daily_resurface WHERE day != ?1")
+            .bind(&today)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("could not delete resurfaced notes {}", e))?;
+
+        for (rank, note_id) in note_ids.iter().enumerate() {
+            sqlx::query("INSERT INTO daily_resurface (day, note_id, rank) VALUES (?1, ?2, ?3)")
+                .bind(&today)
+                .bind(note_id)
+                .bind(rank as i64)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("could not save resurfaced notes {}", e))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| format!("could not save resurfaced notes {}", e))?;
+    }
+
+    note_ids.truncate(limit.unwrap_or(3).clamp(0, DAILY_PICKS) as usize);
+
+    fetch_notes_by_ids(db, &note_ids).await
+}
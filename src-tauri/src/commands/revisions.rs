@@ -0,0 +1,386 @@
+use crate::commands::notes::update_note;
+use crate::commands::settings::{
+    read_setting_or, REVISION_INTERVAL_MINUTES, REVISION_MAX_PER_NOTE, REVISION_RETENTION_DAYS,
+};
+use crate::{AppState, Db};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use sqlx::FromRow;
+use std::fmt::Debug;
+use tauri::Manager;
+
+const DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct NoteRevision {
+    id: i64,
+    note_id: i64,
+    content: String,
+    title: Option<String>,
+    is_checkpoint: bool,
+    label: Option<String>,
+    created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffLineKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiffLine {
+    kind: DiffLineKind,
+    content: String,
+    /// 1-based line number in the older text, None for inserted lines
+    old_line: Option<usize>,
+    /// 1-based line number in the newer text, None for deleted lines
+    new_line: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NoteDiff {
+    lines: Vec<DiffLine>,
+    insertions: usize,
+    deletions: usize,
+}
+
+/// Store the current content of a note as a revision
+pub(crate) async fn insert_note_revision(
+    db: &Db,
+    note_id: i64,
+    is_checkpoint: bool,
+    label: Option<&str>,
+) -> Result<i64, String> {
+    let (id,): (i64,) = sqlx::query_as(
+        "
+        INSERT INTO note_revisions (note_id, content, title, is_checkpoint, label)
+        SELECT id, content, title, ?2, ?3 FROM notes WHERE id = ?1
+        RETURNING id
+        ",
+    )
+    .bind(note_id)
+    .bind(is_checkpoint)
+    .bind(label)
+    .fetch_one(db)
+    .await
+    .map_err(|e| format!("could not save note revision {}", e))?;
+
+    Ok(id)
+}
+
+/// Delete revisions past `revision_retention_days` or beyond
+/// `revision_max_per_note`, of one note or of all of them. Checkpoints are
+/// kept.
+pub async fn prune_note_revisions(db: &Db, note_id: Option<i64>) -> Result<(), String> {
+    let retention_days = read_setting_or(db, REVISION_RETENTION_DAYS, 90i64).await;
+    let max_per_note = read_setting_or(db, REVISION_MAX_PER_NOTE, 50i64).await;
+
+    if retention_days > 0 {
+        sqlx::query(
+            "
+            DELETE FROM note_revisions
+            WHERE is_checkpoint = 0 AND created_at < ?1 AND (?2 IS NULL OR note_id = ?2)
+            ",
+        )
+        .bind(chrono::Utc::now().timestamp() - retention_days * DAY)
+        .bind(note_id)
+        .execute(db)
+        .await
+        .map_err(|e| format!("could not prune note revisions {}", e))?;
+    }
+
+    if max_per_note > 0 {
+        sqlx::query(
+            "
+            DELETE FROM note_revisions
+            WHERE id IN (
+                SELECT id FROM (
+                    SELECT id, ROW_NUMBER() OVER (
+                        PARTITION BY note_id ORDER BY created_at DESC, id DESC
+                    ) AS newness
+                    FROM note_revisions
+                    WHERE is_checkpoint = 0 AND (?2 IS NULL OR note_id = ?2)
+                )
+                WHERE newness > ?1
+            )
+            ",
+        )
+        .bind(max_per_note)
+        .bind(note_id)
+        .execute(db)
+        .await
+        .map_err(|e| format!("could not prune note revisions {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Keep the content a note has before it is replaced with `new_content`.
+/// Edits within `revision_interval_minutes` of the last revision don't get
+/// their own.
+pub async fn snapshot_note_before_update(
+    db: &Db,
+    note_id: i64,
+    new_content: &str,
+) -> Result<(), String> {
+    let (content,): (String,) = sqlx::query_as("SELECT content FROM notes WHERE id = ?1")
+        .bind(note_id)
+        .fetch_one(db)
+        .await
+        .map_err(|e| format!("Failed to get note {}", e))?;
+    if content == new_content {
+        return Ok(());
+    }
+
+    let interval_minutes = read_setting_or(db, REVISION_INTERVAL_MINUTES, 10i64).await;
+    let (last_revision_at,): (Option<i64>,) =
+        sqlx::query_as("SELECT MAX(created_at) FROM note_revisions WHERE note_id = ?1")
+            .bind(note_id)
+            .fetch_one(db)
+            .await
+            .map_err(|e| format!("Failed to get note revisions {}", e))?;
+
+    let now = chrono::Utc::now().timestamp();
+    if last_revision_at.is_some_and(|at| now - at < interval_minutes * 60) {
+        return Ok(());
+    }
+
+    insert_note_revision(db, note_id, false, None).await?;
+    prune_note_revisions(db, Some(note_id)).await
+}
+
+async fn fetch_note_revision(db: &Db, id: i64) -> Result<NoteRevision, String> {
+    sqlx::query_as::<_, NoteRevision>(
+        "
+        SELECT id, note_id, content, title, is_checkpoint, label, created_at
+        FROM note_revisions
+        WHERE id = ?1
+        ",
+    )
+    .bind(id)
+    .fetch_one(db)
+    .await
+    .map_err(|e| format!("Failed to get note revision {}", e))
+}
+
+/// Revisions of a note, the newest first
+#[tauri::command]
+pub async fn get_note_revisions(
+    state: tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<Vec<NoteRevision>, String> {
+    sqlx::query_as::<_, NoteRevision>(
+        "
+        SELECT id, note_id, content, title, is_checkpoint, label, created_at
+        FROM note_revisions
+        WHERE note_id = ?1
+        ORDER BY created_at DESC, id DESC
+        ",
+    )
+    .bind(note_id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| format!("Failed to get note revisions {}", e))
+}
+
+/// Save the current content of a note as a checkpoint, regardless of when
+/// the last revision was taken
+#[tauri::command]
+pub async fn create_note_checkpoint(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    note_id: i64,
+    label: Option<String>,
+) -> Result<i64, String> {
+    let label = label
+        .map(|label| label.trim().to_string())
+        .filter(|label| !label.is_empty());
+
+    let id = insert_note_revision(&state.db, note_id, true, label.as_deref()).await?;
+
+    let _ = app_handle.emit_all("refetch_note_revisions", "");
+
+    Ok(id)
+}
+
+/// Line diff from `old` to `new`
+fn diff_lines(old: &str, new: &str) -> NoteDiff {
+    let diff = TextDiff::from_lines(old, new);
+
+    let mut lines: Vec<DiffLine> = vec![];
+    let mut insertions = 0;
+    let mut deletions = 0;
+
+    for change in diff.iter_all_changes() {
+        let kind = match change.tag() {
+            ChangeTag::Equal => DiffLineKind::Equal,
+            ChangeTag::Insert => {
+                insertions += 1;
+                DiffLineKind::Insert
+            }
+            ChangeTag::Delete => {
+                deletions += 1;
+                DiffLineKind::Delete
+            }
+        };
+
+        lines.push(DiffLine {
+            kind,
+            content: change.value().trim_end_matches(['\n', '\r']).to_string(),
+            old_line: change.old_index().map(|index| index + 1),
+            new_line: change.new_index().map(|index| index + 1),
+        });
+    }
+
+    NoteDiff {
+        lines,
+        insertions,
+        deletions,
+    }
+}
+
+/// Line diff from one revision to another, or to the current content of the
+/// note when `to_id` is missing
+#[tauri::command]
+pub async fn diff_note_revisions(
+    state: tauri::State<'_, AppState>,
+    from_id: i64,
+    to_id: Option<i64>,
+) -> Result<NoteDiff, String> {
+    let db = &state.db;
+
+    let from = fetch_note_revision(db, from_id).await?;
+    let to_content = match to_id {
+        Some(to_id) => fetch_note_revision(db, to_id).await?.content,
+        None => {
+            let (content,): (String,) = sqlx::query_as("SELECT content FROM notes WHERE id = ?1")
+                .bind(from.note_id)
+                .fetch_one(db)
+                .await
+                .map_err(|e| format!("Failed to get note {}", e))?;
+            content
+        }
+    };
+
+    Ok(diff_lines(&from.content, &to_content))
+}
+
+// A title set by hand comes back with the content of the revision, a
+// generated one is generated again from it
+async fn restore_revision_title(db: &Db, revision: &NoteRevision) -> Result<(), String> {
+    if let Some(title) = &revision.title {
+        sqlx::query("UPDATE notes SET title = ?1 WHERE id = ?2 AND title_is_custom")
+            .bind(title)
+            .bind(revision.note_id)
+            .execute(db)
+            .await
+            .map_err(|e| format!("could not restore note title {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Bring back the content of a revision and reindex the note, along with its
+/// title when the note has a custom one. The content being replaced is kept
+/// as a revision first, so a restore can be undone.
+#[tauri::command]
+pub async fn restore_note_revision(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+) -> Result<(), String> {
+    let revision = fetch_note_revision(&state.db, id).await?;
+
+    insert_note_revision(
+        &state.db,
+        revision.note_id,
+        false,
+        Some(&format!("Before restoring revision {}", id)),
+    )
+    .await?;
+
+    let _ = app_handle.emit_all("refetch_note_revisions", "");
+
+    restore_revision_title(&state.db, &revision).await?;
+
+    update_note(app_handle, state, revision.note_id, revision.content).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup_test_db;
+
+    #[tokio::test]
+    async fn custom_titles_are_restored() {
+        let db = setup_test_db().await;
+
+        for title_is_custom in [true, false] {
+            let note_id: i64 = sqlx::query_scalar(
+                "INSERT INTO notes (content, title, title_is_custom) VALUES ('old', 'Old title', ?1) RETURNING id",
+            )
+            .bind(title_is_custom)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+            let revision_id = insert_note_revision(&db, note_id, false, None)
+                .await
+                .unwrap();
+            sqlx::query("UPDATE notes SET content = 'new', title = 'New title' WHERE id = ?1")
+                .bind(note_id)
+                .execute(&db)
+                .await
+                .unwrap();
+
+            let revision = fetch_note_revision(&db, revision_id).await.unwrap();
+            restore_revision_title(&db, &revision).await.unwrap();
+        }
+
+        let titles: Vec<String> = sqlx::query_scalar("SELECT title FROM notes ORDER BY id")
+            .fetch_all(&db)
+            .await
+            .unwrap();
+        assert_eq!(titles, vec!["Old title", "New title"]);
+    }
+
+    #[test]
+    fn diff_numbers_lines_of_both_sides() {
+        let diff = diff_lines("a\nb\nc\n", "a\nc\nd\n");
+
+        let lines: Vec<(DiffLineKind, &str, Option<usize>, Option<usize>)> = diff
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    line.kind,
+                    line.content.as_str(),
+                    line.old_line,
+                    line.new_line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (DiffLineKind::Equal, "a", Some(1), Some(1)),
+                (DiffLineKind::Delete, "b", Some(2), None),
+                (DiffLineKind::Equal, "c", Some(3), Some(2)),
+                (DiffLineKind::Insert, "d", None, Some(3)),
+            ]
+        );
+        assert_eq!(diff.insertions, 1);
+        assert_eq!(diff.deletions, 1);
+    }
+
+    #[test]
+    fn diff_of_a_line_without_newline() {
+        let diff = diff_lines("a", "a\r\nb");
+
+        assert_eq!(diff.insertions, 2);
+        assert_eq!(diff.deletions, 1);
+        assert!(diff.lines.iter().all(|line| !line.content.ends_with('\r')));
+    }
+}
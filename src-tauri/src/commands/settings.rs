@@ -0,0 +1,158 @@
+use crate::sentence_encoder::SentenceEncoderBackend;
+use crate::{AppState, Db};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+use tauri::Manager;
+
+/// Sentence encoder used for new embeddings, applied on the next launch
+pub const EMBEDDING_BACKEND: &str = "embedding_backend";
+/// Sentence encoder the stored note embeddings were created with
+pub const INDEXED_EMBEDDING_BACKEND: &str = "indexed_embedding_backend";
+/// Whether notes saved before language detection existed got a language
+pub const LANGUAGES_BACKFILLED: &str = "languages_backfilled";
+/// How chunks are weighted in a note centroid, `uniform` or `chunk_length`
+pub const CENTROID_WEIGHTING: &str = "centroid_weighting";
+/// Number of centroids stored per note, 1 only keeps the average
+pub const NOTE_CENTROIDS_K: &str = "note_centroids_k";
+/// GGUF model used to answer questions, defaults to `<models_dir>/llm/model.gguf`
+pub const LLM_MODEL_PATH: &str = "llm_model_path";
+/// Tokenizer of the GGUF model, defaults to `<models_dir>/llm/tokenizer.json`
+pub const LLM_TOKENIZER_PATH: &str = "llm_tokenizer_path";
+/// Maximum number of tokens generated per answer
+pub const LLM_MAX_TOKENS: &str = "llm_max_tokens";
+/// Notes assigned to a topic since all topics were last recomputed
+pub const TOPIC_CHANGES_SINCE_RECOMPUTE: &str = "topic_changes_since_recompute";
+/// Notes placed on the map since its projection was last fitted
+pub const NOTE_MAP_CHANGES_SINCE_FIT: &str = "note_map_changes_since_fit";
+/// Whether suggested tags above `tag_auto_apply_threshold` are added to notes
+pub const TAG_AUTO_APPLY: &str = "tag_auto_apply";
+/// Confidence from 0 to 1 a suggested tag needs to be applied automatically
+pub const TAG_AUTO_APPLY_THRESHOLD: &str = "tag_auto_apply_threshold";
+/// Days a note has to go unopened before it can be resurfaced
+pub const RESURFACE_STALE_DAYS: &str = "resurface_stale_days";
+/// Minutes between two revisions taken of a note while it is edited
+pub const REVISION_INTERVAL_MINUTES: &str = "revision_interval_minutes";
+/// Days revisions are kept, 0 keeps them forever. Checkpoints are never pruned.
+pub const REVISION_RETENTION_DAYS: &str = "revision_retention_days";
+/// Revisions kept per note besides checkpoints, 0 keeps all of them
+pub const REVISION_MAX_PER_NOTE: &str = "revision_max_per_note";
+/// Days trashed notes are kept before they are purged, 0 keeps them until the
+/// trash is emptied
+pub const TRASH_RETENTION_DAYS: &str = "trash_retention_days";
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct Setting {
+    key: String,
+    value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbeddingBackendStatus {
+    /// Backend chosen in the settings
+    selected: SentenceEncoderBackend,
+    /// Backend the running sentence encoder was spawned with
+    active: SentenceEncoderBackend,
+    /// Backend the stored embeddings were created with
+    indexed: SentenceEncoderBackend,
+}
+
+pub async fn read_setting(db: &Db, key: &str) -> Result<Option<String>, String> {
+    let value: Option<(String,)> = sqlx::query_as("SELECT value FROM settings WHERE key = ?1")
+        .bind(key)
+        .fetch_optional(db)
+        .await
+        .map_err(|e| format!("Failed to read setting {} {}", key, e))?;
+
+    Ok(value.map(|(value,)| value))
+}
+
+/// Read a setting and parse it, falling back to `default` when it is missing
+/// or can not be parsed
+pub async fn read_setting_or<T: FromStr>(db: &Db, key: &str, default: T) -> T {
+    match read_setting(db, key).await {
+        Ok(Some(value)) => value.parse().unwrap_or(default),
+        _ => default,
+    }
+}
+
+pub async fn write_setting(db: &Db, key: &str, value: &str) -> Result<(), String> {
+    sqlx::query("INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)")
+        .bind(key)
+        .bind(value)
+        .execute(db)
+        .await
+        .map_err(|e| format!("Failed to write setting {} {}", key, e))?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_settings(
+    state: tauri::State<'_, AppState>,
+) -> Result<HashMap<String, String>, String> {
+    let db = &state.db;
+
+    let settings: Vec<Setting> = sqlx::query_as::<_, Setting>("SELECT key, value FROM settings")
+        .fetch_all(db)
+        .await
+        .map_err(|e| format!("Failed to get settings {}", e))?;
+
+    Ok(settings
+        .into_iter()
+        .map(|setting| (setting.key, setting.value))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn update_setting(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    key: String,
+    value: String,
+) -> Result<(), String> {
+    write_setting(&state.db, &key, &value).await?;
+
+    let _ = app_handle.emit_all("refetch_settings", "");
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_embedding_backend(
+    state: tauri::State<'_, AppState>,
+) -> Result<EmbeddingBackendStatus, String> {
+    let db = &state.db;
+
+    Ok(EmbeddingBackendStatus {
+        selected: read_setting_or(db, EMBEDDING_BACKEND, SentenceEncoderBackend::default()).await,
+        active: state.sentence_encoder_backend,
+        indexed: read_setting_or(
+            db,
+            INDEXED_EMBEDDING_BACKEND,
+            SentenceEncoderBackend::default(),
+        )
+        .await,
+    })
+}
+
+#[tauri::command]
+pub async fn set_embedding_backend(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    backend: SentenceEncoderBackend,
+) -> Result<(), String> {
+    if !backend.is_available(&state.models_dir) {
+        return Err(format!(
+            "Model for {} not found in {}",
+            backend.as_str(),
+            state.models_dir.to_string_lossy()
+        ));
+    }
+
+    write_setting(&state.db, EMBEDDING_BACKEND, backend.as_str()).await?;
+
+    let _ = app_handle.emit_all("refetch_settings", "");
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use crate::commands::links::{rename_links_to_note, resolve_links_to_note_title};
+use crate::AppState;
+use tauri::Manager;
+
+const TITLE_MAX_CHARS: usize = 80;
+// Shorter notes are their own summary
+const MIN_WORDS_TO_SUMMARIZE: usize = 50;
+
+fn strip_markdown_line(line: &str) -> &str {
+    line.trim()
+        .trim_start_matches(|c: char| c == '#' || c == '>' || c == '-' || c == '*')
+        .trim()
+}
+
+fn first_heading(content: &str) -> Option<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with('#'))
+        .map(strip_markdown_line)
+        .find(|heading| !heading.is_empty())
+        .map(String::from)
+}
+
+fn first_line(content: &str) -> Option<String> {
+    content
+        .lines()
+        .map(strip_markdown_line)
+        .find(|line| !line.is_empty())
+        .map(String::from)
+}
+
+fn first_sentence(text: &str) -> Option<String> {
+    text.split_inclusive(|c: char| c == '.' || c == '!' || c == '?' || c == '。' || c == '\n')
+        .map(|sentence| sentence.trim().trim_end_matches('.').trim())
+        .find(|sentence| !sentence.is_empty())
+        .map(String::from)
+}
+
+// Cut long titles at the last word that fits
+fn truncate_title(title: String) -> String {
+    if title.chars().count() <= TITLE_MAX_CHARS {
+        return title;
+    }
+
+    let truncated: String = title.chars().take(TITLE_MAX_CHARS).collect();
+    let truncated = match truncated.rfind(char::is_whitespace) {
+        Some(index) if index > 0 => truncated[..index].to_string(),
+        _ => truncated,
+    };
+
+    format!("{}…", truncated.trim_end())
+}
+
+/// Summarize the content with the local summarization model and derive a
+/// title from the summary. Falls back to the first heading, then the first
+/// line, when the note is too short or the model is unavailable.
+async fn generate_title_and_summary(
+    state: &tauri::State<'_, AppState>,
+    content: &str,
+) -> (Option<String>, Option<String>) {
+    let summary = if content.split_whitespace().count() >= MIN_WORDS_TO_SUMMARIZE {
+        match state.summarizer.summarize(content.to_string()).await {
+            Ok(summary) => Some(summary.trim().to_string()).filter(|summary| !summary.is_empty()),
+            Err(err) => {
+                log::warn!("could not summarize note {}", err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let title = summary
+        .as_deref()
+        .and_then(first_sentence)
+        .or_else(|| first_heading(content))
+        .or_else(|| first_line(content))
+        .map(truncate_title);
+
+    (title, summary)
+}
+
+/// Regenerate the summary of a note, and its title unless the user set one
+pub async fn refresh_note_title_and_summary(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let (content,): (String,) = sqlx::query_as("SELECT content FROM notes WHERE id = ?1")
+        .bind(note_id)
+        .fetch_one(db)
+        .await
+        .map_err(|e| format!("Failed to get note {}", e))?;
+
+    let (title, summary) = generate_title_and_summary(state, &content).await;
+
+    let (new_title,): (Option<String>,) = sqlx::query_as(
+        "
+        UPDATE notes
+        SET summary = ?1,
+            title = CASE WHEN title_is_custom THEN title ELSE ?2 END
+        WHERE id = ?3
+        RETURNING title
+        ",
+    )
+    .bind(summary)
+    .bind(title)
+    .bind(note_id)
+    .fetch_one(db)
+    .await
+    .map_err(|e| format!("could not update note {}", e))?;
+
+    // Generated titles don't rewrite the links to the note, only explicit
+    // renames do
+    resolve_links_to_note_title(db, note_id, new_title.as_deref()).await
+}
+
+#[tauri::command]
+pub async fn regenerate_note_title_and_summary(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+) -> Result<(), String> {
+    refresh_note_title_and_summary(&state, id).await?;
+
+    let _ = app_handle.emit_all("refetch_notes", "");
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn regenerate_all_note_titles_and_summaries(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let note_ids: Vec<(i64,)> = sqlx::query_as("SELECT id FROM notes")
+        .fetch_all(db)
+        .await
+        .map_err(|e| format!("Failed to get notes {}", e))?;
+
+    for (note_id,) in note_ids {
+        refresh_note_title_and_summary(&state, note_id).await?;
+    }
+
+    let _ = app_handle.emit_all("refetch_notes", "");
+
+    Ok(())
+}
+
+/// Override the generated title of a note. Passing no title goes back to a
+/// generated one.
+#[tauri::command]
+pub async fn set_note_title(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    title: Option<String>,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let title = title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty());
+
+    match title {
+        Some(title) => {
+            let (old_title,): (Option<String>,) =
+                sqlx::query_as("SELECT title FROM notes WHERE id = ?1")
+                    .bind(id)
+                    .fetch_one(db)
+                    .await
+                    .map_err(|e| format!("Failed to get note {}", e))?;
+
+            sqlx::query("UPDATE notes SET title = ?1, title_is_custom = 1 WHERE id = ?2")
+                .bind(&title)
+                .bind(id)
+                .execute(db)
+                .await
+                .map_err(|e| format!("could not update note title {}", e))?;
+
+            rename_links_to_note(&app_handle, &state, id, old_title.as_deref(), &title).await?;
+        }
+        None => {
+            sqlx::query("UPDATE notes SET title_is_custom = 0 WHERE id = ?1")
+                .bind(id)
+                .execute(db)
+                .await
+                .map_err(|e| format!("could not update note title {}", e))?;
+
+            refresh_note_title_and_summary(&state, id).await?;
+        }
+    }
+
+    let _ = app_handle.emit_all("refetch_notes", "");
+
+    Ok(())
+}
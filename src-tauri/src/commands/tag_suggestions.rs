@@ -0,0 +1,327 @@
+use crate::commands::notes::convert_blob_to_vec_f32;
+use crate::commands::settings::{read_setting_or, TAG_AUTO_APPLY, TAG_AUTO_APPLY_THRESHOLD};
+use crate::vector_math::{cosine_similarity, weighted_centroid};
+use crate::zero_shot_classifier::ClassificationRequest;
+use crate::{AppState, Db};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use tauri::Manager;
+
+// Tags on fewer notes than this are matched by zero-shot classification, a
+// centroid of one or two notes says little about a tag
+const MIN_TAG_EXAMPLES: usize = 3;
+const TAG_SUGGESTIONS: usize = 5;
+// Below this a suggestion isn't worth showing
+const MIN_CONFIDENCE: f64 = 0.3;
+// Each label is a pass of the classifier over the note, the most used tags
+// are tried first
+const MAX_ZERO_SHOT_TAGS: usize = 20;
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct TagSuggestion {
+    tag_id: i64,
+    tag_path: String,
+    /// From 0 to 1
+    confidence: f64,
+    /// `centroid` or `zero_shot`
+    source: String,
+}
+
+#[derive(Debug, FromRow)]
+struct TaggedNote {
+    tag_id: i64,
+    note_id: i64,
+    embedding: Vec<u8>,
+}
+
+// Tags of every note that has an embedding, trashed notes left out
+async fn get_tagged_notes(db: &Db) -> Result<Vec<TaggedNote>, String> {
+    sqlx::query_as::<_, TaggedNote>(
+        "
+        SELECT notes_to_tags.tag_id, notes.id AS note_id, notes.average_sentence_embedding AS embedding
+        FROM notes_to_tags
+        JOIN notes ON notes.id = notes_to_tags.note_id
+        WHERE notes.average_sentence_embedding IS NOT NULL AND notes.deleted_at IS NULL
+        ",
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get tagged notes {}", e))
+}
+
+/// Score every tag the note doesn't have yet. Tags with enough notes are
+/// compared through the centroid of their notes. With `zero_shot`, up to
+/// `MAX_ZERO_SHOT_TAGS` of the others are scored by classifying the note
+/// content with the tag paths as labels.
+async fn compute_tag_suggestions(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+    zero_shot: bool,
+) -> Result<Vec<TagSuggestion>, String> {
+    let db = &state.db;
+
+    let (content, average): (String, Option<Vec<u8>>) =
+        sqlx::query_as("SELECT content, average_sentence_embedding FROM notes WHERE id = ?1")
+            .bind(note_id)
+            .fetch_one(db)
+            .await
+            .map_err(|e| format!("Failed to get note {}", e))?;
+
+    let note_embedding = match average {
+        Some(average) => convert_blob_to_vec_f32(average).unwrap(),
+        None => return Ok(vec![]),
+    };
+
+    let applied: Vec<(i64,)> =
+        sqlx::query_as("SELECT tag_id FROM notes_to_tags WHERE note_id = ?1")
+            .bind(note_id)
+            .fetch_all(db)
+            .await
+            .map_err(|e| format!("Failed to get note tags {}", e))?;
+    let applied: HashSet<i64> = applied.into_iter().map(|(tag_id,)| tag_id).collect();
+
+    let all_tags: Vec<(i64, String)> = sqlx::query_as("SELECT id, path FROM tag_paths")
+        .fetch_all(db)
+        .await
+        .map_err(|e| format!("Failed to get tags {}", e))?;
+
+    let tagged_notes = get_tagged_notes(db).await?;
+
+    // The note itself must not vote for its own tags
+    let mut embeddings_by_tag: HashMap<i64, Vec<Vec<f32>>> = HashMap::new();
+    for tagged_note in tagged_notes {
+        if tagged_note.note_id == note_id {
+            continue;
+        }
+        embeddings_by_tag
+            .entry(tagged_note.tag_id)
+            .or_default()
+            .push(convert_blob_to_vec_f32(tagged_note.embedding).unwrap());
+    }
+
+    let mut suggestions: Vec<TagSuggestion> = vec![];
+    // Tags with too few notes, with their number of notes
+    let mut zero_shot_candidates: Vec<(i64, String, usize)> = vec![];
+
+    for (tag_id, tag_path) in all_tags {
+        if applied.contains(&tag_id) {
+            continue;
+        }
+
+        let embeddings = embeddings_by_tag.remove(&tag_id).unwrap_or_default();
+        if embeddings.len() < MIN_TAG_EXAMPLES {
+            zero_shot_candidates.push((tag_id, tag_path, embeddings.len()));
+            continue;
+        }
+
+        let vectors: Vec<&Vec<f32>> = embeddings.iter().collect();
+        let weights = vec![1.0; vectors.len()];
+        if let Some(centroid) = weighted_centroid(&vectors, &weights) {
+            suggestions.push(TagSuggestion {
+                tag_id,
+                tag_path,
+                confidence: cosine_similarity(&note_embedding, &centroid).clamp(0.0, 1.0) as f64,
+                source: "centroid".into(),
+            });
+        }
+    }
+
+    // Zero-shot labels are tag paths, mapped back to their tag afterwards
+    zero_shot_candidates.sort_by(|a, b| b.2.cmp(&a.2));
+    let zero_shot_tags: HashMap<String, i64> = zero_shot_candidates
+        .into_iter()
+        .take(MAX_ZERO_SHOT_TAGS)
+        .map(|(tag_id, tag_path, _)| (tag_path, tag_id))
+        .collect();
+
+    if zero_shot && !zero_shot_tags.is_empty() {
+        let request = ClassificationRequest {
+            texts: vec![content],
+            labels: zero_shot_tags.keys().cloned().collect(),
+            hypothesis_template: None,
+        };
+
+        match state.zero_shot_classifier.classify(request).await {
+            Ok(labels) => {
+                for label in labels.into_iter().flatten() {
+                    if let Some(&tag_id) = zero_shot_tags.get(&label.text) {
+                        suggestions.push(TagSuggestion {
+                            tag_id,
+                            tag_path: label.text,
+                            confidence: label.score,
+                            source: "zero_shot".into(),
+                        });
+                    }
+                }
+            }
+            // Centroid suggestions are still worth returning
+            Err(err) => log::warn!("could not classify note {} {}", note_id, err),
+        }
+    }
+
+    suggestions.retain(|suggestion| suggestion.confidence >= MIN_CONFIDENCE);
+    suggestions.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    suggestions.truncate(TAG_SUGGESTIONS);
+
+    Ok(suggestions)
+}
+
+/// Recompute and store the tag suggestions of a note. Without `zero_shot`
+/// the stored zero-shot suggestions are kept, classifying is too slow to run
+/// on every save. With `tag_auto_apply` enabled, suggestions above the
+/// threshold are added to the note right away. Returns whether tags were
+/// applied.
+pub async fn update_note_tag_suggestions(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+    zero_shot: bool,
+) -> Result<bool, String> {
+    let db = &state.db;
+
+    let suggestions = compute_tag_suggestions(state, note_id, zero_shot).await?;
+
+    let auto_apply = read_setting_or(db, TAG_AUTO_APPLY, false).await;
+    let threshold = read_setting_or(db, TAG_AUTO_APPLY_THRESHOLD, 0.8f64).await;
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    sqlx::query(
+        "DELETE FROM note_tag_suggestions WHERE note_id = ?1 AND (?2 OR source != 'zero_shot')",
+    )
+    .bind(note_id)
+    .bind(zero_shot)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("could not delete tag suggestions {}", e))?;
+
+    let mut applied = false;
+
+    for suggestion in &suggestions {
+        if auto_apply && suggestion.confidence >= threshold {
+            sqlx::query("INSERT OR IGNORE INTO notes_to_tags (note_id, tag_id) VALUES (?1, ?2)")
+                .bind(note_id)
+                .bind(suggestion.tag_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("could not apply tag {}", e))?;
+            applied = true;
+            continue;
+        }
+
+        sqlx::query(
+            "
+            INSERT OR REPLACE INTO note_tag_suggestions (note_id, tag_id, confidence, source)
+            VALUES (?1, ?2, ?3, ?4)
+            ",
+        )
+        .bind(note_id)
+        .bind(suggestion.tag_id)
+        .bind(suggestion.confidence)
+        .bind(&suggestion.source)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not insert tag suggestion {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not save tag suggestions {}", e))?;
+
+    Ok(applied)
+}
+
+/// Tags the note is likely to need, the most confident first. Suggestions are
+/// computed when the note is saved. Classifying the note against tags with
+/// too few notes only happens here, on the first call or with `refresh`.
+#[tauri::command]
+pub async fn suggest_tags(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    note_id: i64,
+    refresh: Option<bool>,
+) -> Result<Vec<TagSuggestion>, String> {
+    let db = &state.db;
+
+    let (has_suggestions,): (bool,) =
+        sqlx::query_as("SELECT EXISTS (SELECT 1 FROM note_tag_suggestions WHERE note_id = ?1)")
+            .bind(note_id)
+            .fetch_one(db)
+            .await
+            .map_err(|e| format!("Failed to get tag suggestions {}", e))?;
+
+    let applied = if refresh.unwrap_or(false) || !has_suggestions {
+        update_note_tag_suggestions(&state, note_id, true).await?
+    } else {
+        false
+    };
+    if applied {
+        let _ = app_handle.emit_all("refetch_tags", "");
+        let _ = app_handle.emit_all("refetch_notes", "");
+    }
+
+    // Tags added since the suggestions were computed are left out
+    sqlx::query_as::<_, TagSuggestion>(
+        "
+        SELECT s.tag_id, tp.path AS tag_path, s.confidence, s.source
+        FROM note_tag_suggestions s
+        JOIN tag_paths tp ON tp.id = s.tag_id
+        WHERE s.note_id = ?1
+            AND s.tag_id NOT IN (SELECT tag_id FROM notes_to_tags WHERE note_id = ?1)
+        ORDER BY s.confidence DESC
+        ",
+    )
+    .bind(note_id)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get tag suggestions {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup_test_db;
+
+    #[tokio::test]
+    async fn trashed_notes_dont_vote_for_tags() {
+        let db = setup_test_db().await;
+
+        let tag_id: i64 =
+            sqlx::query_scalar("INSERT INTO tags (name) VALUES ('rust') RETURNING id")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+
+        let mut note_ids = vec![];
+        for _ in 0..2 {
+            let note_id: i64 = sqlx::query_scalar(
+                "INSERT INTO notes (content, average_sentence_embedding) VALUES ('', '[1.0]') RETURNING id",
+            )
+            .fetch_one(&db)
+            .await
+            .unwrap();
+            sqlx::query("INSERT INTO notes_to_tags (note_id, tag_id) VALUES (?1, ?2)")
+                .bind(note_id)
+                .bind(tag_id)
+                .execute(&db)
+                .await
+                .unwrap();
+            note_ids.push(note_id);
+        }
+
+        sqlx::query("UPDATE notes SET deleted_at = 1 WHERE id = ?1")
+            .bind(note_ids[1])
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let tagged_notes = get_tagged_notes(&db).await.unwrap();
+        assert_eq!(tagged_notes.len(), 1);
+        assert_eq!(tagged_notes[0].note_id, note_ids[0]);
+        assert_eq!(tagged_notes[0].tag_id, tag_id);
+    }
+}
@@ -1,75 +1,270 @@
 use crate::AppState;
 use futures::TryStreamExt;
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, SqliteConnection};
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use tauri::Manager;
 
 #[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
 pub struct Tag {
-    pub id: String,
+    pub id: i64,
+    pub name: String,
+    pub parent_id: Option<i64>,
+    /// Names from the top level tag down, e.g. `work/infra/db`
+    pub path: String,
 }
 
+/// A tag with its metadata and how much it is used. Counts leave out
+/// archived and trashed notes.
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct TagDetails {
+    id: i64,
+    name: String,
+    parent_id: Option<i64>,
+    path: String,
+    colour: Option<String>,
+    icon: Option<String>,
+    description: Option<String>,
+    created_at: i64,
+    /// Notes with the tag itself
+    note_count: i64,
+    /// Notes with the tag or any tag below it
+    total_note_count: i64,
+    /// When the tag or a tag below it was last added to a note
+    last_used_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TagSort {
+    /// By path, so tags come right after their parent
+    Name,
+    Count,
+    Recent,
+}
+
+/// Subquery selecting the ids of a tag and all tags below it. `tag_id` is
+/// the SQL expression of the tag, e.g. `?2` or `4`.
+pub fn tag_subtree_query(tag_id: &str) -> String {
+    format!(
+        "
+        WITH RECURSIVE subtree(id) AS (
+            SELECT {}
+            UNION ALL
+            SELECT tags.id FROM tags JOIN subtree ON tags.parent_id = subtree.id
+        )
+        SELECT id FROM subtree
+        ",
+        tag_id
+    )
+}
+
+/// Condition on a note having the tags bound from `?{first_param}` on, or
+/// tags below them. `note_id` is the SQL expression of the note, e.g. `n.id`.
+/// None without tags.
+pub fn tag_filter_condition(
+    note_id: &str,
+    tag_count: usize,
+    first_param: usize,
+    match_all: bool,
+) -> Option<String> {
+    if tag_count == 0 {
+        return None;
+    }
+
+    let conditions: Vec<String> = (first_param..first_param + tag_count)
+        .map(|param| {
+            format!(
+                "EXISTS (SELECT 1 FROM notes_to_tags x WHERE x.note_id = {} AND x.tag_id IN ({}))",
+                note_id,
+                tag_subtree_query(&format!("?{}", param))
+            )
+        })
+        .collect();
+    let operator = if match_all { " AND " } else { " OR " };
+
+    Some(format!("({})", conditions.join(operator)))
+}
+
+async fn get_tag_subtree(conn: &mut SqliteConnection, tag_id: i64) -> Result<Vec<i64>, String> {
+    let ids: Vec<(i64,)> = sqlx::query_as(&tag_subtree_query("?1"))
+        .bind(tag_id)
+        .fetch_all(conn)
+        .await
+        .map_err(|e| format!("Failed to get tags {}", e))?;
+
+    Ok(ids.into_iter().map(|(id,)| id).collect())
+}
+
+async fn find_child_tag(
+    conn: &mut SqliteConnection,
+    parent_id: Option<i64>,
+    name: &str,
+) -> Result<Option<i64>, String> {
+    let found: Option<(i64,)> = sqlx::query_as(
+        "SELECT id FROM tags WHERE COALESCE(parent_id, 0) = COALESCE(?1, 0) AND name = ?2",
+    )
+    .bind(parent_id)
+    .bind(name)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| format!("Failed to get tag {}", e))?;
+
+    Ok(found.map(|(id,)| id))
+}
+
+/// Id of the tag at `path` such as `work/infra/db`, creating the tags along
+/// the way that don't exist yet
+pub async fn find_or_create_tag_path(
+    conn: &mut SqliteConnection,
+    path: &str,
+) -> Result<i64, String> {
+    let mut parent_id: Option<i64> = None;
+
+    for name in path
+        .split('/')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let id = match find_child_tag(conn, parent_id, name).await? {
+            Some(id) => id,
+            None => {
+                sqlx::query("INSERT INTO tags (name, parent_id, created_at) VALUES (?1, ?2, ?3)")
+                    .bind(name)
+                    .bind(parent_id)
+                    .bind(chrono::Utc::now().timestamp())
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(|e| format!("could not create tag {}", e))?
+                    .last_insert_rowid()
+            }
+        };
+        parent_id = Some(id);
+    }
+
+    parent_id.ok_or(format!("Tag name can not be empty: {:?}", path))
+}
+
+/// Add a tag to a note. Names with `/` create nested tags, e.g.
+/// `work/infra/db`. Returns the id of the tag.
 #[tauri::command]
 pub async fn create_tag(
     app_handle: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
     name: String,
     note_id: i64,
-) -> Result<String, String> {
+) -> Result<i64, String> {
     let db = &state.db;
 
-    sqlx::query(
-        "
-        INSERT OR REPLACE INTO tags (id) VALUES (?1);
-        INSERT INTO notes_to_tags (note_id, tag_id) VALUES (?2, ?3);
-    ",
-    )
-    .bind(&name)
-    .bind(&note_id)
-    .bind(&name)
-    .execute(db)
-    .await
-    .unwrap();
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    let tag_id = find_or_create_tag_path(&mut tx, &name).await?;
+
+    sqlx::query("INSERT OR IGNORE INTO notes_to_tags (note_id, tag_id) VALUES (?1, ?2)")
+        .bind(note_id)
+        .bind(tag_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not add tag {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not add tag {}", e))?;
 
     let _ = app_handle.emit_all("refetch_tags", "");
-    Ok(name)
+    Ok(tag_id)
 }
 
+/// Tags with their metadata and usage, by default sorted by path. With
+/// `note_id` only the tags of that note. With `tag_ids` only the tags on
+/// notes matching that filter, like `get_notes` does, counted over those
+/// notes, so a tag list can be narrowed down.
 #[tauri::command]
 pub async fn get_tags(
     state: tauri::State<'_, AppState>,
     note_id: Option<i64>,
     take: Option<u32>,
     skip: Option<u32>,
-) -> Result<Vec<Tag>, String> {
+    sort: Option<TagSort>,
+    tag_ids: Option<Vec<i64>>,
+    match_all: Option<bool>,
+) -> Result<Vec<TagDetails>, String> {
     let db = &state.db;
 
-    let base_query_str = if let Some(note_id) = note_id {
-        format!(
-            "
-            SELECT *
-            FROM tags
-            JOIN notes_to_tags ON tags.id = notes_to_tags.tag_id
-            WHERE notes_to_tags.note_id = {}
-            LIMIT ?1
-            OFFSET ?2
-            ",
-            note_id
-        )
-    } else {
-        format!(
-            "
-            SELECT * FROM tags
-            LIMIT ?1
-            OFFSET ?2
+    let tag_ids = tag_ids.unwrap_or_default();
+    // Filter tags are bound after take, skip and note_id
+    let filter = tag_filter_condition("n.id", tag_ids.len(), 4, match_all.unwrap_or(false));
+    let (usage_filter, tag_filter) = match &filter {
+        Some(filter) => (
+            format!("AND {}", filter),
+            // Tags on none of the matching notes are left out
+            "AND usage.tag_id IS NOT NULL",
+        ),
+        None => (String::new(), ""),
+    };
+
+    let order = match sort.unwrap_or(TagSort::Name) {
+        TagSort::Name => "tag_paths.path",
+        TagSort::Count => "total_note_count DESC, tag_paths.path",
+        TagSort::Recent => "usage.last_used_at DESC NULLS LAST, tag_paths.path",
+    };
+
+    let query_str = format!(
         "
+        WITH RECURSIVE ancestry(ancestor_id, tag_id) AS (
+            SELECT id, id FROM tags
+            UNION ALL
+            SELECT ancestry.ancestor_id, tags.id FROM tags JOIN ancestry ON tags.parent_id = ancestry.tag_id
+        ),
+        usage AS (
+            SELECT
+                ancestry.ancestor_id AS tag_id,
+                COUNT(DISTINCT CASE WHEN nt.tag_id = ancestry.ancestor_id THEN nt.note_id END) AS note_count,
+                COUNT(DISTINCT nt.note_id) AS total_note_count,
+                MAX(nt.created_at) AS last_used_at
+            FROM ancestry
+            JOIN notes_to_tags nt ON nt.tag_id = ancestry.tag_id
+            JOIN notes n ON n.id = nt.note_id
+            WHERE n.deleted_at IS NULL AND n.archived = 0 {}
+            GROUP BY ancestry.ancestor_id
         )
-    };
+        SELECT
+            tags.id,
+            tags.name,
+            tags.parent_id,
+            tag_paths.path,
+            tags.colour,
+            tags.icon,
+            tags.description,
+            tags.created_at,
+            COALESCE(usage.note_count, 0) AS note_count,
+            COALESCE(usage.total_note_count, 0) AS total_note_count,
+            usage.last_used_at
+        FROM tags
+        JOIN tag_paths ON tag_paths.id = tags.id
+        LEFT JOIN usage ON usage.tag_id = tags.id
+        WHERE (?3 IS NULL OR tags.id IN (SELECT tag_id FROM notes_to_tags WHERE note_id = ?3))
+            {}
+        ORDER BY {}
+        LIMIT ?1
+        OFFSET ?2
+        ",
+        usage_filter, tag_filter, order
+    );
 
-    let tags: Vec<Tag> = sqlx::query_as::<_, Tag>(&base_query_str)
+    let mut query = sqlx::query_as::<_, TagDetails>(&query_str)
         .bind(take.unwrap_or(50))
         .bind(skip.unwrap_or(0))
+        .bind(note_id);
+    for tag_id in &tag_ids {
+        query = query.bind(tag_id);
+    }
+
+    let tags: Vec<TagDetails> = query
         .fetch(db)
         .try_collect()
         .await
@@ -78,20 +273,207 @@ pub async fn get_tags(
     Ok(tags)
 }
 
+/// Set the colour, icon and description of a tag. Missing values are
+/// cleared.
+#[tauri::command]
+pub async fn update_tag(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    colour: Option<String>,
+    icon: Option<String>,
+    description: Option<String>,
+) -> Result<(), String> {
+    let [colour, icon, description] = [colour, icon, description].map(|value| {
+        value
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    });
+
+    sqlx::query("UPDATE tags SET colour = ?1, icon = ?2, description = ?3 WHERE id = ?4")
+        .bind(colour)
+        .bind(icon)
+        .bind(description)
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| format!("could not update tag {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_tags", "");
+    Ok(())
+}
+
+/// Delete a tag together with the tags below it
 #[tauri::command]
 pub async fn delete_tag(
     app_handle: tauri::AppHandle,
     state: tauri::State<'_, AppState>,
-    id: String,
+    id: i64,
 ) -> Result<(), String> {
     let db = &state.db;
 
     sqlx::query("DELETE FROM tags WHERE id = ?1")
-        .bind(&id)
+        .bind(id)
         .execute(db)
         .await
-        .unwrap();
+        .map_err(|e| format!("could not delete tag {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_tags", "");
+    let _ = app_handle.emit_all("refetch_notes", "");
+    Ok(())
+}
+
+/// Rename a tag. A name with `/` is a full path and moves the tag there,
+/// e.g. `personal/db`. Notes keep the tag.
+#[tauri::command]
+pub async fn rename_tag(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    name: String,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let name = name.trim().trim_matches('/');
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    let (parent_id,): (Option<i64>,) = sqlx::query_as("SELECT parent_id FROM tags WHERE id = ?1")
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to get tag {}", e))?;
+
+    let (parent_id, name) = match name.rsplit_once('/') {
+        Some((parent_path, name)) => {
+            let parent_id = find_or_create_tag_path(&mut tx, parent_path).await?;
+            if get_tag_subtree(&mut tx, id).await?.contains(&parent_id) {
+                return Err("A tag can not be moved below itself".to_string());
+            }
+            (Some(parent_id), name.trim())
+        }
+        None => (parent_id, name),
+    };
+    if name.is_empty() {
+        return Err("Tag name can not be empty".to_string());
+    }
+
+    if find_child_tag(&mut tx, parent_id, name)
+        .await?
+        .is_some_and(|existing_id| existing_id != id)
+    {
+        return Err(format!(
+            "Tag {} already exists, merge the tags instead",
+            name
+        ));
+    }
+
+    sqlx::query("UPDATE tags SET name = ?1, parent_id = ?2 WHERE id = ?3")
+        .bind(name)
+        .bind(parent_id)
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not rename tag {}", e))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not rename tag {}", e))?;
+
+    let _ = app_handle.emit_all("refetch_tags", "");
+    let _ = app_handle.emit_all("refetch_notes", "");
+    Ok(())
+}
+
+/// Merge tags into the tag `id`: their notes get the tag `id` and the
+/// merged tags are deleted. Tags below a merged tag move below `id`, into the
+/// tag of the same name when there is one.
+#[tauri::command]
+pub async fn merge_tags(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    tag_ids: Vec<i64>,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    // Pairs of (merged tag, tag it is merged into)
+    let mut pending: VecDeque<(i64, i64)> = VecDeque::new();
+    for tag_id in tag_ids {
+        if tag_id == id || pending.iter().any(|(merged_id, _)| *merged_id == tag_id) {
+            continue;
+        }
+        if get_tag_subtree(&mut tx, tag_id).await?.contains(&id) {
+            return Err("A tag can not be merged into a tag below it".to_string());
+        }
+        pending.push_back((tag_id, id));
+    }
+
+    let mut merged_ids: Vec<i64> = vec![];
+
+    while let Some((merged_id, into_id)) = pending.pop_front() {
+        sqlx::query(
+            "
+            INSERT OR IGNORE INTO notes_to_tags (note_id, tag_id, created_at)
+            SELECT note_id, ?2, created_at FROM notes_to_tags WHERE tag_id = ?1;
+            DELETE FROM notes_to_tags WHERE tag_id = ?1;
+            INSERT OR IGNORE INTO note_tag_suggestions (note_id, tag_id, confidence, source)
+            SELECT note_id, ?2, confidence, source FROM note_tag_suggestions WHERE tag_id = ?1;
+            DELETE FROM note_tag_suggestions WHERE tag_id = ?1;
+            ",
+        )
+        .bind(merged_id)
+        .bind(into_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not merge tag notes {}", e))?;
+
+        let children: Vec<(i64, String)> =
+            sqlx::query_as("SELECT id, name FROM tags WHERE parent_id = ?1")
+                .bind(merged_id)
+                .fetch_all(&mut *tx)
+                .await
+                .map_err(|e| format!("Failed to get tags {}", e))?;
+
+        for (child_id, child_name) in children {
+            match find_child_tag(&mut tx, Some(into_id), &child_name).await? {
+                Some(existing_id) => pending.push_back((child_id, existing_id)),
+                None => {
+                    sqlx::query("UPDATE tags SET parent_id = ?1 WHERE id = ?2")
+                        .bind(into_id)
+                        .bind(child_id)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|e| format!("could not move tag {}", e))?;
+                }
+            }
+        }
+
+        merged_ids.push(merged_id);
+    }
+
+    // Children first, deleting a parent would take them along
+    for merged_id in merged_ids.iter().rev() {
+        sqlx::query("DELETE FROM tags WHERE id = ?1")
+            .bind(merged_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("could not delete tag {}", e))?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not merge tags {}", e))?;
 
     let _ = app_handle.emit_all("refetch_tags", "");
+    let _ = app_handle.emit_all("refetch_notes", "");
     Ok(())
 }
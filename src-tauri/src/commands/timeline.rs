@@ -0,0 +1,243 @@
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+// A topic needs this many notes in a period to count as emerging
+const MIN_EMERGING_NOTES: i64 = 2;
+// ...and a share of the period this many times its share until then
+const EMERGING_GROWTH: f64 = 2.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineGranularity {
+    Week,
+    Month,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineDateField {
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimelineParams {
+    granularity: TimelineGranularity,
+    date_field: Option<TimelineDateField>,
+    /// Seconds since Unix epoch
+    from: Option<i64>,
+    to: Option<i64>,
+    /// Topics and tags kept per period, most active first
+    take: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TopicActivity {
+    topic_id: i64,
+    label: String,
+    note_count: i64,
+    /// Part of the period's notes in this topic
+    share: f64,
+    /// Much more active than in the previous periods
+    emerging: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagActivity {
+    tag_id: i64,
+    tag_path: String,
+    note_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TimelinePeriod {
+    /// First day of the week (YYYY-MM-DD) or the month (YYYY-MM)
+    period: String,
+    note_count: i64,
+    topics: Vec<TopicActivity>,
+    tags: Vec<TagActivity>,
+}
+
+#[derive(Debug, FromRow)]
+struct PeriodCount {
+    period: String,
+    note_count: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct PeriodTopicCount {
+    period: String,
+    topic_id: i64,
+    label: String,
+    note_count: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct PeriodTagCount {
+    period: String,
+    tag_id: i64,
+    tag_path: String,
+    note_count: i64,
+}
+
+/// How the mix of topics and tags changes over time. Notes are bucketed by
+/// week or month of their creation (or last update) and each period lists
+/// its most active topics and tags, flagging topics that are emerging.
+#[tauri::command]
+pub async fn get_topic_timeline(
+    state: tauri::State<'_, AppState>,
+    params: TimelineParams,
+) -> Result<Vec<TimelinePeriod>, String> {
+    let db = &state.db;
+
+    let date_column = match params.date_field.unwrap_or(TimelineDateField::CreatedAt) {
+        TimelineDateField::CreatedAt => "n.created_at",
+        TimelineDateField::UpdatedAt => "n.updated_at",
+    };
+    let period_expression = match params.granularity {
+        // Weeks start on Monday
+        TimelineGranularity::Week => {
+            format!("date({}, 'unixepoch', 'weekday 0', '-6 days')", date_column)
+        }
+        TimelineGranularity::Month => format!("strftime('%Y-%m', {}, 'unixepoch')", date_column),
+    };
+    let range_condition = format!("{} BETWEEN ?1 AND ?2 AND n.deleted_at IS NULL", date_column);
+    let from = params.from.unwrap_or(0);
+    let to = params.to.unwrap_or(i64::MAX);
+    let take = params.take.unwrap_or(5);
+
+    let period_counts: Vec<PeriodCount> = sqlx::query_as::<_, PeriodCount>(&format!(
+        "
+        SELECT {} AS period, COUNT(*) AS note_count
+        FROM notes n
+        WHERE {}
+        GROUP BY period
+        ORDER BY period
+        ",
+        period_expression, range_condition
+    ))
+    .bind(from)
+    .bind(to)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get timeline {}", e))?;
+
+    let topic_counts: Vec<PeriodTopicCount> = sqlx::query_as::<_, PeriodTopicCount>(&format!(
+        "
+        SELECT {} AS period, t.id AS topic_id, t.label, COUNT(*) AS note_count
+        FROM notes n
+        JOIN notes_to_topics nt ON nt.note_id = n.id
+        JOIN topics t ON t.id = nt.topic_id
+        WHERE {}
+        GROUP BY period, t.id
+        ",
+        period_expression, range_condition
+    ))
+    .bind(from)
+    .bind(to)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get timeline topics {}", e))?;
+
+    let tag_counts: Vec<PeriodTagCount> = sqlx::query_as::<_, PeriodTagCount>(&format!(
+        "
+        SELECT {} AS period, nt.tag_id, tp.path AS tag_path, COUNT(*) AS note_count
+        FROM notes n
+        JOIN notes_to_tags nt ON nt.note_id = n.id
+        JOIN tag_paths tp ON tp.id = nt.tag_id
+        WHERE {}
+        GROUP BY period, nt.tag_id
+        ",
+        period_expression, range_condition
+    ))
+    .bind(from)
+    .bind(to)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get timeline tags {}", e))?;
+
+    let mut topics_by_period: HashMap<String, Vec<PeriodTopicCount>> = HashMap::new();
+    for count in topic_counts {
+        topics_by_period
+            .entry(count.period.clone())
+            .or_default()
+            .push(count);
+    }
+    let mut tags_by_period: HashMap<String, Vec<PeriodTagCount>> = HashMap::new();
+    for count in tag_counts {
+        tags_by_period
+            .entry(count.period.clone())
+            .or_default()
+            .push(count);
+    }
+
+    // Notes seen per topic, and in total, before the current period
+    let mut previous_topic_counts: HashMap<i64, i64> = HashMap::new();
+    let mut previous_note_count: i64 = 0;
+    let mut timeline: Vec<TimelinePeriod> = vec![];
+
+    for period_count in period_counts {
+        let period_topics = topics_by_period
+            .remove(&period_count.period)
+            .unwrap_or_default();
+
+        let mut topics: Vec<TopicActivity> = period_topics
+            .iter()
+            .map(|count| {
+                let share = count.note_count as f64 / period_count.note_count as f64;
+                let previous_count = previous_topic_counts
+                    .get(&count.topic_id)
+                    .copied()
+                    .unwrap_or(0);
+                let previous_share = if previous_note_count > 0 {
+                    previous_count as f64 / previous_note_count as f64
+                } else {
+                    0.0
+                };
+
+                TopicActivity {
+                    topic_id: count.topic_id,
+                    label: count.label.clone(),
+                    note_count: count.note_count,
+                    share,
+                    // Without history every topic would look new
+                    emerging: previous_note_count > 0
+                        && count.note_count >= MIN_EMERGING_NOTES
+                        && share >= EMERGING_GROWTH * previous_share,
+                }
+            })
+            .collect();
+        topics.sort_by(|a, b| b.note_count.cmp(&a.note_count));
+        topics.truncate(take);
+
+        let mut tags: Vec<TagActivity> = tags_by_period
+            .remove(&period_count.period)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|count| TagActivity {
+                tag_id: count.tag_id,
+                tag_path: count.tag_path,
+                note_count: count.note_count,
+            })
+            .collect();
+        tags.sort_by(|a, b| b.note_count.cmp(&a.note_count));
+        tags.truncate(take);
+
+        for count in &period_topics {
+            *previous_topic_counts.entry(count.topic_id).or_insert(0) += count.note_count;
+        }
+        previous_note_count += period_count.note_count;
+
+        timeline.push(TimelinePeriod {
+            period: period_count.period,
+            note_count: period_count.note_count,
+            topics,
+            tags,
+        });
+    }
+
+    Ok(timeline)
+}
@@ -0,0 +1,414 @@
+use crate::commands::notes::{
+    convert_blob_to_vec_f32, fetch_notes_by_ids, get_note_embeddings, sentence_embedding_to_json,
+    Note,
+};
+use crate::commands::settings::{read_setting_or, write_setting, TOPIC_CHANGES_SINCE_RECOMPUTE};
+use crate::vector_math::{kmeans, nearest_center, squared_euclidean_distance, weighted_centroid};
+use crate::{AppState, Db};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fmt::Debug;
+use tauri::Manager;
+
+const KMEANS_MAX_ITERATIONS: usize = 50;
+const MAX_TOPICS: usize = 30;
+// Below this there is not much to group
+const MIN_NOTES_FOR_TOPICS: usize = 6;
+const TOPIC_KEYWORDS: i64 = 5;
+const TOPIC_LABEL_KEYWORDS: usize = 3;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Topic {
+    id: i64,
+    label: String,
+    keywords: Vec<String>,
+    note_count: i64,
+    updated_at: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct TopicRow {
+    id: i64,
+    label: String,
+    keywords: String,
+    note_count: i64,
+    updated_at: i64,
+}
+
+#[derive(Debug, FromRow)]
+struct EmbeddingRow {
+    id: i64,
+    embedding: Vec<u8>,
+}
+
+async fn get_topic_centroids(db: &Db) -> Result<Vec<(i64, Vec<f32>)>, String> {
+    let rows: Vec<EmbeddingRow> =
+        sqlx::query_as::<_, EmbeddingRow>("SELECT id, centroid AS embedding FROM topics")
+            .fetch(db)
+            .try_collect()
+            .await
+            .map_err(|e| format!("Failed to get topics {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.id, convert_blob_to_vec_f32(row.embedding).unwrap()))
+        .collect())
+}
+
+/// Words of `word_embeddings.sqlite` closest to a topic centroid
+async fn find_topic_keywords(
+    state: &tauri::State<'_, AppState>,
+    centroid: &[f32],
+) -> Result<Vec<String>, String> {
+    let words: Vec<(String,)> = sqlx::query_as(
+        r#"
+        WITH matches AS (
+            SELECT
+                rowid,
+                distance
+            FROM vec_words
+            WHERE sentence_embedding MATCH ?1
+            ORDER BY distance
+            LIMIT ?2
+        )
+        SELECT words.text
+        FROM matches
+        JOIN words ON words.id = matches.rowid
+        ORDER BY matches.distance
+        "#,
+    )
+    .bind(sentence_embedding_to_json(centroid))
+    .bind(TOPIC_KEYWORDS)
+    .fetch_all(&state.word_embeddings_db)
+    .await
+    .map_err(|e| format!("Failed to search words {}", e))?;
+
+    Ok(words.into_iter().map(|(text,)| text).collect())
+}
+
+async fn label_topic(
+    state: &tauri::State<'_, AppState>,
+    topic_id: i64,
+    centroid: &[f32],
+) -> Result<(), String> {
+    let keywords = find_topic_keywords(state, centroid).await?;
+    let label = keywords
+        .iter()
+        .take(TOPIC_LABEL_KEYWORDS)
+        .cloned()
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    sqlx::query("UPDATE topics SET label = ?1, keywords = ?2 WHERE id = ?3")
+        .bind(label)
+        .bind(serde_json::to_string(&keywords).unwrap())
+        .bind(topic_id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| format!("could not label topic {}", e))?;
+
+    Ok(())
+}
+
+/// Group all notes by their average sentence embedding with k-means and label
+/// every group with its closest words. Replaces the previous topics.
+pub async fn recompute_all_topics(state: &tauri::State<'_, AppState>) -> Result<(), String> {
+    let db = &state.db;
+
+    let notes = get_note_embeddings(db).await?;
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    sqlx::query("DELETE FROM notes_to_topics; DELETE FROM topics;")
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("could not delete topics {}", e))?;
+
+    let mut topics: Vec<(i64, Vec<f32>)> = vec![];
+
+    if notes.len() >= MIN_NOTES_FOR_TOPICS {
+        // Rule of thumb for the number of clusters
+        let k = ((notes.len() as f32 / 2.0).sqrt().round() as usize).clamp(2, MAX_TOPICS);
+
+        let vectors: Vec<&Vec<f32>> = notes.iter().map(|(_, embedding)| embedding).collect();
+        let weights = vec![1.0; vectors.len()];
+        let centroids: Vec<Vec<f32>> = kmeans(&vectors, &weights, k, KMEANS_MAX_ITERATIONS)
+            .into_iter()
+            .map(|(centroid, _)| centroid)
+            .collect();
+
+        for centroid in &centroids {
+            let inserted_topic = sqlx::query("INSERT INTO topics (centroid) VALUES (?1)")
+                .bind(sentence_embedding_to_json(centroid))
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("could not insert topic {}", e))?;
+
+            topics.push((inserted_topic.last_insert_rowid(), centroid.clone()));
+        }
+
+        for (note_id, embedding) in &notes {
+            let nearest = nearest_center(embedding, &centroids);
+
+            sqlx::query(
+                "INSERT INTO notes_to_topics (note_id, topic_id, distance) VALUES (?1, ?2, ?3)",
+            )
+            .bind(note_id)
+            .bind(topics[nearest].0)
+            .bind(squared_euclidean_distance(embedding, &centroids[nearest]).sqrt())
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("could not assign note to topic {}", e))?;
+        }
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not save topics {}", e))?;
+
+    for (topic_id, centroid) in &topics {
+        label_topic(state, *topic_id, centroid).await?;
+    }
+
+    write_setting(db, TOPIC_CHANGES_SINCE_RECOMPUTE, "0").await?;
+
+    Ok(())
+}
+
+// Average sentence embeddings of the notes of a topic, trashed notes left out
+async fn get_topic_note_embeddings(db: &Db, topic_id: i64) -> Result<Vec<Vec<f32>>, String> {
+    let rows: Vec<(Vec<u8>,)> = sqlx::query_as(
+        "
+        SELECT notes.average_sentence_embedding
+        FROM notes_to_topics
+        JOIN notes ON notes.id = notes_to_topics.note_id
+        WHERE notes_to_topics.topic_id = ?1
+            AND notes.average_sentence_embedding IS NOT NULL
+            AND notes.deleted_at IS NULL
+        ",
+    )
+    .bind(topic_id)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get topic notes {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(blob,)| convert_blob_to_vec_f32(blob).unwrap())
+        .collect())
+}
+
+// Move the centroid of a topic to the mean of its current notes
+async fn refresh_topic_centroid(
+    state: &tauri::State<'_, AppState>,
+    topic_id: i64,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let embeddings = get_topic_note_embeddings(db, topic_id).await?;
+    let vectors: Vec<&Vec<f32>> = embeddings.iter().collect();
+    let weights = vec![1.0; vectors.len()];
+
+    let centroid = match weighted_centroid(&vectors, &weights) {
+        Some(centroid) => centroid,
+        None => return Ok(()),
+    };
+
+    sqlx::query("UPDATE topics SET centroid = ?1, updated_at = ?2 WHERE id = ?3")
+        .bind(sentence_embedding_to_json(&centroid))
+        .bind(chrono::Utc::now().timestamp())
+        .bind(topic_id)
+        .execute(db)
+        .await
+        .map_err(|e| format!("could not update topic {}", e))?;
+
+    label_topic(state, topic_id, &centroid).await
+}
+
+/// Assign a new or changed note to its closest topic. Topics are recomputed
+/// from scratch once enough notes changed since the last full clustering, or
+/// when there are none yet.
+pub async fn update_note_topic(
+    state: &tauri::State<'_, AppState>,
+    note_id: i64,
+) -> Result<(), String> {
+    let db = &state.db;
+
+    let topics = get_topic_centroids(db).await?;
+    let (note_count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM notes WHERE deleted_at IS NULL")
+            .fetch_one(db)
+            .await
+            .map_err(|e| format!("Failed to count notes {}", e))?;
+
+    let changes = read_setting_or(db, TOPIC_CHANGES_SINCE_RECOMPUTE, 0i64).await + 1;
+    let max_changes = (note_count / 4).max(10);
+
+    if topics.is_empty() || changes > max_changes {
+        return recompute_all_topics(state).await;
+    }
+
+    write_setting(db, TOPIC_CHANGES_SINCE_RECOMPUTE, &changes.to_string()).await?;
+
+    let (blob,): (Vec<u8>,) =
+        sqlx::query_as("SELECT average_sentence_embedding FROM notes WHERE id = ?1")
+            .bind(note_id)
+            .fetch_one(db)
+            .await
+            .map_err(|e| format!("Failed to get note {}", e))?;
+    let embedding = convert_blob_to_vec_f32(blob).unwrap();
+
+    let centroids: Vec<Vec<f32>> = topics
+        .iter()
+        .map(|(_, centroid)| centroid.clone())
+        .collect();
+    let nearest = nearest_center(&embedding, &centroids);
+    let topic_id = topics[nearest].0;
+
+    let previous_topic_id: Option<(i64,)> =
+        sqlx::query_as("SELECT topic_id FROM notes_to_topics WHERE note_id = ?1")
+            .bind(note_id)
+            .fetch_optional(db)
+            .await
+            .map_err(|e| format!("Failed to get note topic {}", e))?;
+
+    sqlx::query(
+        "INSERT OR REPLACE INTO notes_to_topics (note_id, topic_id, distance) VALUES (?1, ?2, ?3)",
+    )
+    .bind(note_id)
+    .bind(topic_id)
+    .bind(squared_euclidean_distance(&embedding, &centroids[nearest]).sqrt())
+    .execute(db)
+    .await
+    .map_err(|e| format!("could not assign note to topic {}", e))?;
+
+    refresh_topic_centroid(state, topic_id).await?;
+    if let Some((previous_topic_id,)) = previous_topic_id {
+        if previous_topic_id != topic_id {
+            refresh_topic_centroid(state, previous_topic_id).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn fetch_topics(db: &Db) -> Result<Vec<Topic>, String> {
+    let rows: Vec<TopicRow> = sqlx::query_as::<_, TopicRow>(
+        "
+        SELECT topics.id, topics.label, topics.keywords, topics.updated_at, COUNT(notes.id) AS note_count
+        FROM topics
+        LEFT JOIN notes_to_topics ON notes_to_topics.topic_id = topics.id
+        LEFT JOIN notes ON notes.id = notes_to_topics.note_id AND notes.deleted_at IS NULL
+        GROUP BY topics.id
+        ORDER BY note_count DESC
+        ",
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get topics {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Topic {
+            id: row.id,
+            label: row.label,
+            keywords: serde_json::from_str(&row.keywords).unwrap_or_default(),
+            note_count: row.note_count,
+            updated_at: row.updated_at,
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn get_topics(state: tauri::State<'_, AppState>) -> Result<Vec<Topic>, String> {
+    fetch_topics(&state.db).await
+}
+
+/// Notes of a topic, the most representative first
+#[tauri::command]
+pub async fn get_notes_in_topic(
+    state: tauri::State<'_, AppState>,
+    topic_id: i64,
+) -> Result<Vec<Note>, String> {
+    let db = &state.db;
+
+    let note_ids: Vec<(i64,)> = sqlx::query_as(
+        "SELECT note_id FROM notes_to_topics WHERE topic_id = ?1 ORDER BY distance ASC",
+    )
+    .bind(topic_id)
+    .fetch_all(db)
+    .await
+    .map_err(|e| format!("Failed to get topic notes {}", e))?;
+
+    let note_ids: Vec<i64> = note_ids.into_iter().map(|(id,)| id).collect();
+
+    fetch_notes_by_ids(db, &note_ids).await
+}
+
+#[tauri::command]
+pub async fn recompute_topics(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    recompute_all_topics(&state).await?;
+
+    let _ = app_handle.emit_all("refetch_topics", "");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup_test_db;
+
+    async fn insert_topic_note(db: &Db, topic_id: i64, embedding: &[f32]) -> i64 {
+        let note_id: i64 = sqlx::query_scalar(
+            "INSERT INTO notes (content, average_sentence_embedding) VALUES ('', ?1) RETURNING id",
+        )
+        .bind(sentence_embedding_to_json(embedding))
+        .fetch_one(db)
+        .await
+        .unwrap();
+
+        sqlx::query("INSERT INTO notes_to_topics (note_id, topic_id, distance) VALUES (?1, ?2, 0)")
+            .bind(note_id)
+            .bind(topic_id)
+            .execute(db)
+            .await
+            .unwrap();
+
+        note_id
+    }
+
+    #[tokio::test]
+    async fn trashed_notes_leave_their_topic() {
+        let db = setup_test_db().await;
+
+        let topic_id: i64 =
+            sqlx::query_scalar("INSERT INTO topics (centroid) VALUES ('[0.5,0.5]') RETURNING id")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        insert_topic_note(&db, topic_id, &[1.0, 0.0]).await;
+        let trashed = insert_topic_note(&db, topic_id, &[0.0, 1.0]).await;
+
+        sqlx::query("UPDATE notes SET deleted_at = 1 WHERE id = ?1")
+            .bind(trashed)
+            .execute(&db)
+            .await
+            .unwrap();
+
+        let topics = fetch_topics(&db).await.unwrap();
+        assert_eq!(topics.len(), 1);
+        assert_eq!(topics[0].note_count, 1);
+        assert_eq!(
+            get_topic_note_embeddings(&db, topic_id).await.unwrap(),
+            vec![vec![1.0, 0.0]]
+        );
+    }
+}
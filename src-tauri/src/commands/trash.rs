@@ -0,0 +1,258 @@
+use crate::commands::links::resolve_links_to_note;
+use crate::commands::notes::delete_note_vector_embeddings;
+use crate::commands::settings::{read_setting_or, TRASH_RETENTION_DAYS};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, SqliteConnection};
+use std::fmt::Debug;
+use tauri::Manager;
+
+const DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Serialize, Deserialize, Clone, FromRow)]
+pub struct TrashedNote {
+    id: i64,
+    title: Option<String>,
+    content: String,
+    updated_at: i64,
+    deleted_at: i64,
+}
+
+/// Move a note to the trash. Its chunks leave the KNN index, so they don't
+/// take the place of other notes in searches, but are kept for a restore.
+pub async fn trash_note(conn: &mut SqliteConnection, note_id: i64) -> Result<(), String> {
+    sqlx::query(
+        "
+        DELETE FROM vec_note_chunks
+        WHERE rowid IN (
+            SELECT note_chunks.id
+            FROM note_chunks
+            JOIN notes ON notes.id = note_chunks.note_id
+            WHERE note_chunks.note_id = ?1 AND notes.deleted_at IS NULL
+        );
+        UPDATE notes SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL;
+        ",
+    )
+    .bind(note_id)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(conn)
+    .await
+    .map_err(|e| format!("could not trash note {}", e))?;
+
+    Ok(())
+}
+
+/// Take a note out of the trash and put its chunks back in the KNN index
+pub async fn untrash_note(conn: &mut SqliteConnection, note_id: i64) -> Result<(), String> {
+    sqlx::query(
+        "
+        INSERT INTO vec_note_chunks (rowid, sentence_embedding)
+        SELECT note_chunks.id, note_chunks.sentence_embedding
+        FROM note_chunks
+        JOIN notes ON notes.id = note_chunks.note_id
+        WHERE note_chunks.note_id = ?1
+            AND note_chunks.sentence_embedding IS NOT NULL
+            AND notes.deleted_at IS NOT NULL;
+        UPDATE notes SET deleted_at = NULL WHERE id = ?1;
+        ",
+    )
+    .bind(note_id)
+    .execute(conn)
+    .await
+    .map_err(|e| format!("could not restore note {}", e))?;
+
+    Ok(())
+}
+
+/// Delete a note for good, with its chunks and their vectors
+async fn purge_note(state: &tauri::State<'_, AppState>, note_id: i64) -> Result<(), String> {
+    let db = &state.db;
+
+    // vec_note_chunks has no foreign key, so the chunks go first
+    delete_note_vector_embeddings(state, note_id).await?;
+
+    sqlx::query(
+        "
+        DELETE FROM notes_to_tags WHERE note_id = ?1;
+        DELETE FROM notes WHERE id = ?1;
+        ",
+    )
+    .bind(note_id)
+    .execute(db)
+    .await
+    .map_err(|e| format!("could not delete note {}", e))?;
+
+    Ok(())
+}
+
+/// Purge notes trashed more than `trash_retention_days` ago. Returns the
+/// number of purged notes.
+pub async fn purge_expired_notes(state: &tauri::State<'_, AppState>) -> Result<usize, String> {
+    let db = &state.db;
+
+    let retention_days = read_setting_or(db, TRASH_RETENTION_DAYS, 30i64).await;
+    if retention_days <= 0 {
+        return Ok(0);
+    }
+
+    let expired: Vec<(i64,)> =
+        sqlx::query_as("SELECT id FROM notes WHERE deleted_at IS NOT NULL AND deleted_at < ?1")
+            .bind(chrono::Utc::now().timestamp() - retention_days * DAY)
+            .fetch_all(db)
+            .await
+            .map_err(|e| format!("Failed to get trashed notes {}", e))?;
+
+    for (note_id,) in &expired {
+        purge_note(state, *note_id).await?;
+    }
+
+    Ok(expired.len())
+}
+
+/// Notes in the trash, the most recently trashed first
+#[tauri::command]
+pub async fn get_trashed_notes(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<TrashedNote>, String> {
+    sqlx::query_as::<_, TrashedNote>(
+        "
+        SELECT id, title, content, updated_at, deleted_at
+        FROM notes
+        WHERE deleted_at IS NOT NULL
+        ORDER BY deleted_at DESC
+        ",
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| format!("Failed to get trashed notes {}", e))
+}
+
+/// Take notes out of the trash
+#[tauri::command]
+pub async fn restore_notes(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    note_ids: Vec<i64>,
+) -> Result<(), String> {
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|e| format!("could not start transaction {}", e))?;
+
+    for note_id in &note_ids {
+        untrash_note(&mut tx, *note_id).await?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| format!("could not restore notes {}", e))?;
+
+    for note_id in &note_ids {
+        resolve_links_to_note(&state.db, *note_id).await?;
+    }
+
+    let _ = app_handle.emit_all("refetch_notes", "");
+    let _ = app_handle.emit_all("refetch_trash", "");
+    let _ = app_handle.emit_all("refetch_links", "");
+
+    Ok(())
+}
+
+/// Delete trashed notes for good. Without `note_ids` the whole trash is
+/// emptied. Notes that aren't in the trash are left alone.
+#[tauri::command]
+pub async fn purge_notes(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    note_ids: Option<Vec<i64>>,
+) -> Result<usize, String> {
+    let trashed: Vec<(i64,)> = sqlx::query_as("SELECT id FROM notes WHERE deleted_at IS NOT NULL")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| format!("Failed to get trashed notes {}", e))?;
+
+    let note_ids: Vec<i64> = trashed
+        .into_iter()
+        .map(|(id,)| id)
+        .filter(|id| match &note_ids {
+            Some(note_ids) => note_ids.contains(id),
+            None => true,
+        })
+        .collect();
+
+    for note_id in &note_ids {
+        purge_note(&state, *note_id).await?;
+    }
+
+    let _ = app_handle.emit_all("refetch_trash", "");
+
+    Ok(note_ids.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup_test_db;
+
+    async fn indexed_chunk_count(conn: &mut SqliteConnection, note_id: i64) -> i64 {
+        sqlx::query_scalar(
+            "
+            SELECT COUNT(*)
+            FROM vec_note_chunks
+            WHERE rowid IN (SELECT id FROM note_chunks WHERE note_id = ?1)
+            ",
+        )
+        .bind(note_id)
+        .fetch_one(conn)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn trashed_chunks_leave_the_index_until_restored() {
+        let db = setup_test_db().await;
+        let embedding = serde_json::to_string(&vec![1.0f32; 384]).unwrap();
+
+        let note_id: i64 =
+            sqlx::query_scalar("INSERT INTO notes (content) VALUES ('') RETURNING id")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        for _ in 0..2 {
+            let chunk_id: i64 = sqlx::query_scalar(
+                "INSERT INTO note_chunks (sentence, sentence_embedding, note_id) VALUES ('', ?1, ?2) RETURNING id",
+            )
+            .bind(&embedding)
+            .bind(note_id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+            sqlx::query("INSERT INTO vec_note_chunks (rowid, sentence_embedding) VALUES (?1, ?2)")
+                .bind(chunk_id)
+                .bind(&embedding)
+                .execute(&db)
+                .await
+                .unwrap();
+        }
+
+        let mut conn = db.acquire().await.unwrap();
+
+        // Trashing or restoring twice changes nothing more
+        for _ in 0..2 {
+            trash_note(&mut conn, note_id).await.unwrap();
+        }
+        assert_eq!(indexed_chunk_count(&mut conn, note_id).await, 0);
+        let (note_count,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM notes WHERE deleted_at IS NOT NULL")
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+        assert_eq!(note_count, 1);
+
+        for _ in 0..2 {
+            untrash_note(&mut conn, note_id).await.unwrap();
+        }
+        assert_eq!(indexed_chunk_count(&mut conn, note_id).await, 2);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::model_runner::serve_model_requests;
+use rust_bert::pipelines::ner::{Entity, NERModel};
+use std::fmt::Debug;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use tokio::{sync::oneshot, task};
+
+type Message = (
+    Vec<String>,
+    oneshot::Sender<anyhow::Result<Vec<Vec<Entity>>>>,
+);
+
+/// Runner for Named Entity Recognition
+#[derive(Debug, Clone)]
+pub struct EntityRecognizer {
+    sender: mpsc::SyncSender<Message>,
+}
+
+impl EntityRecognizer {
+    /// Spawn a recognizer on a separate thread and return a recognizer
+    /// instance to interact with it. The model is only loaded on the first
+    /// request.
+    pub fn spawn() -> (JoinHandle<anyhow::Result<()>>, EntityRecognizer) {
+        let (sender, receiver) = mpsc::sync_channel(100);
+        let handle = thread::spawn(move || Self::runner(receiver));
+        (handle, EntityRecognizer { sender })
+    }
+
+    /// The recognition runner itself
+    fn runner(receiver: mpsc::Receiver<Message>) -> anyhow::Result<()> {
+        serve_model_requests(
+            receiver,
+            "NER",
+            || NERModel::new(Default::default()),
+            |model, (texts, sender)| {
+                let entities = model.map(|model| model.predict_full_entities(&texts));
+                sender.send(entities).expect("sending entities");
+            },
+        )
+    }
+
+    /// Make the runner find the entities of each text. Entities spanning
+    /// several tokens are merged and their offsets are in characters.
+    pub async fn recognize(&self, texts: Vec<String>) -> anyhow::Result<Vec<Vec<Entity>>> {
+        let (sender, receiver) = oneshot::channel();
+        task::block_in_place(|| self.sender.send((texts, sender)))?;
+        receiver.await?
+    }
+}
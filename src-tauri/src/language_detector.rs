@@ -0,0 +1,17 @@
+use whatlang::detect;
+
+// Below this confidence whatlang is mostly guessing, which happens a lot with
+// short chunks such as a single heading
+const MIN_CONFIDENCE: f64 = 0.5;
+
+/// Detect the language of a text offline and return its ISO 639-3 code
+/// (e.g. "eng", "jpn", "deu")
+pub fn detect_language(text: &str) -> Option<String> {
+    let info = detect(text)?;
+
+    if !info.is_reliable() && info.confidence() < MIN_CONFIDENCE {
+        return None;
+    }
+
+    Some(info.lang().code().to_string())
+}
@@ -2,28 +2,96 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
+mod anki;
 mod commands;
+mod entity_recognizer;
+mod language_detector;
+mod model_runner;
+mod question_answerer;
 mod sentence_encoder;
+mod summarizer;
+mod text_generator;
+mod vector_math;
 mod word_vectors;
+mod zero_shot_classifier;
+use commands::ask::{answer_question, ask_notes};
+use commands::comparison::{compare_notes, explain_similarity};
+use commands::contradictions::find_contradictions;
+use commands::duplicates::{find_duplicates, merge_notes};
+use commands::entities::{
+    get_entities, get_note_entities, get_notes_mentioning_entity, merge_entities, reindex_entities,
+};
+use commands::flashcards::{
+    export_flashcards_to_anki, get_due_flashcards, get_note_flashcards, reindex_flashcards,
+    review_flashcard,
+};
+use commands::keyphrases::get_note_keyphrases;
+use commands::links::{
+    get_backlinks, get_graph, get_outgoing_links, get_unresolved_links, reindex_links,
+};
+use commands::map::{get_note_map, recompute_note_map};
+use commands::notebooks::{
+    create_notebook, delete_notebook, get_notebooks, move_notebook, move_notes_to_notebook,
+    rename_notebook,
+};
 use commands::notes::{
-    create_note, delete_note, delete_note_tag, find_similar_notes, get_note, get_notes,
-    get_similar_words, search_notes, update_note,
+    backfill_note_languages, create_note, delete_note, delete_note_tag, find_similar_notes,
+    get_note, get_notes, get_similar_words, needs_reindex, reindex_all_notes, reindex_notes,
+    search_notes, set_note_archived, set_note_favourite, set_note_pinned, update_note,
+};
+use commands::related::{cancel_related_while_typing, find_related_while_typing};
+use commands::resurface::{get_daily_resurface, record_note_view};
+use commands::revisions::{
+    create_note_checkpoint, diff_note_revisions, get_note_revisions, prune_note_revisions,
+    restore_note_revision,
 };
-use commands::tags::{create_tag, delete_tag, get_tags};
-use sentence_encoder::SentenceEncoder;
+use commands::settings::{
+    get_embedding_backend, get_settings, read_setting_or, set_embedding_backend, update_setting,
+    EMBEDDING_BACKEND,
+};
+use commands::summaries::{
+    regenerate_all_note_titles_and_summaries, regenerate_note_title_and_summary, set_note_title,
+};
+use commands::tag_suggestions::suggest_tags;
+use commands::tags::{create_tag, delete_tag, get_tags, merge_tags, rename_tag, update_tag};
+use commands::timeline::get_topic_timeline;
+use commands::topics::{get_notes_in_topic, get_topics, recompute_topics};
+use commands::trash::{get_trashed_notes, purge_expired_notes, purge_notes, restore_notes};
+use entity_recognizer::EntityRecognizer;
+use question_answerer::QuestionAnswerer;
+use sentence_encoder::{SentenceEncoder, SentenceEncoderBackend};
 use sqlite_vec::sqlite3_vec_init;
 use sqlx::{sqlite::SqlitePoolOptions, Pool, Sqlite};
+use std::collections::HashMap;
 use std::env;
 use std::fs::OpenOptions;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::Mutex;
+use summarizer::Summarizer;
 use tauri::{App, CustomMenuItem, Manager as _, Menu, MenuItem, Submenu, WindowBuilder};
+use text_generator::TextGenerator;
 use word_vectors::get_embeddings_path;
+use zero_shot_classifier::ZeroShotClassifier;
 
 pub struct AppState {
     db: Db,
     word_embeddings_db: Db,
     sentence_encoder: SentenceEncoder,
+    sentence_encoder_backend: SentenceEncoderBackend,
+    text_generator: TextGenerator,
+    question_answerer: QuestionAnswerer,
+    summarizer: Summarizer,
+    entity_recognizer: EntityRecognizer,
+    zero_shot_classifier: ZeroShotClassifier,
+    // Latest find_related_while_typing call, older calls give up
+    related_generation: AtomicU64,
+    // Latest enrichment run of each note, older runs give up
+    enrichment_generations: Mutex<HashMap<i64, u64>>,
+    // Enrichment runs take turns
+    enrichment_lock: tokio::sync::Mutex<()>,
     base_dir: PathBuf,
+    models_dir: PathBuf,
 }
 
 #[cfg(debug_assertions)]
@@ -217,17 +285,78 @@ async fn main() {
         })
         .invoke_handler(tauri::generate_handler![
             get_similar_words,
+            get_note_keyphrases,
             find_similar_notes,
+            explain_similarity,
+            compare_notes,
+            find_contradictions,
+            find_duplicates,
+            find_related_while_typing,
+            cancel_related_while_typing,
+            merge_notes,
             search_notes,
             create_note,
             get_notes,
             get_note,
+            record_note_view,
+            get_daily_resurface,
             update_note,
             delete_note,
             delete_note_tag,
             create_tag,
             delete_tag,
-            get_tags
+            rename_tag,
+            update_tag,
+            merge_tags,
+            get_tags,
+            suggest_tags,
+            reindex_notes,
+            get_settings,
+            update_setting,
+            get_embedding_backend,
+            set_embedding_backend,
+            ask_notes,
+            answer_question,
+            regenerate_note_title_and_summary,
+            regenerate_all_note_titles_and_summaries,
+            set_note_title,
+            get_topics,
+            get_notes_in_topic,
+            recompute_topics,
+            get_note_map,
+            recompute_note_map,
+            get_topic_timeline,
+            get_entities,
+            get_note_entities,
+            get_notes_mentioning_entity,
+            merge_entities,
+            reindex_entities,
+            get_note_flashcards,
+            get_due_flashcards,
+            review_flashcard,
+            reindex_flashcards,
+            export_flashcards_to_anki,
+            get_outgoing_links,
+            get_backlinks,
+            get_unresolved_links,
+            get_graph,
+            reindex_links,
+            get_note_revisions,
+            create_note_checkpoint,
+            diff_note_revisions,
+            restore_note_revision,
+            get_trashed_notes,
+            restore_notes,
+            purge_notes,
+            set_note_pinned,
+            set_note_archived,
+            set_note_favourite,
+            get_notebooks,
+            create_notebook,
+            rename_notebook,
+            move_notebook,
+            delete_notebook,
+            move_notes_to_notebook
         ])
         .build(tauri::generate_context!())
         .expect("error building the app");
@@ -251,17 +380,90 @@ async fn main() {
     let db = setup_db(&app).await;
     let word_embeddings_db = setup_word_embeddings_db(&app).await;
 
-    let (_handle, sentence_encoder) = SentenceEncoder::spawn();
+    let base_dir = app
+        .app_handle()
+        .path_resolver()
+        .app_local_data_dir()
+        .unwrap_or(std::path::PathBuf::new());
+    let models_dir = base_dir.join("models");
+
+    let mut sentence_encoder_backend =
+        read_setting_or(&db, EMBEDDING_BACKEND, SentenceEncoderBackend::default()).await;
+    if !sentence_encoder_backend.is_available(&models_dir) {
+        log::warn!(
+            "sentence encoder {} not found in {:?}, using {}",
+            sentence_encoder_backend.as_str(),
+            models_dir,
+            SentenceEncoderBackend::default().as_str()
+        );
+        sentence_encoder_backend = SentenceEncoderBackend::default();
+    }
+
+    let (_handle, sentence_encoder) =
+        SentenceEncoder::spawn(sentence_encoder_backend, models_dir.clone());
+    let (_handle, text_generator) = TextGenerator::spawn();
+    let (_handle, question_answerer) = QuestionAnswerer::spawn();
+    let (_handle, summarizer) = Summarizer::spawn();
+    let (_handle, entity_recognizer) = EntityRecognizer::spawn();
+    let (_handle, zero_shot_classifier) = ZeroShotClassifier::spawn();
 
     app.manage(AppState {
         db,
         word_embeddings_db,
         sentence_encoder,
-        base_dir: app
-            .app_handle()
-            .path_resolver()
-            .app_local_data_dir()
-            .unwrap_or(std::path::PathBuf::new()),
+        sentence_encoder_backend,
+        text_generator,
+        question_answerer,
+        summarizer,
+        entity_recognizer,
+        zero_shot_classifier,
+        related_generation: AtomicU64::new(0),
+        enrichment_generations: Mutex::new(HashMap::new()),
+        enrichment_lock: tokio::sync::Mutex::new(()),
+        base_dir,
+        models_dir,
+    });
+
+    // Embeddings from another backend are not comparable with new ones
+    let app_handle = app.app_handle();
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        if needs_reindex(&state).await {
+            log::info!(
+                "reindexing notes with {}",
+                state.sentence_encoder_backend.as_str()
+            );
+            match reindex_all_notes(&state).await {
+                Ok(_) => {
+                    let _ = app_handle.emit_all("refetch_notes", "");
+                }
+                Err(err) => log::error!("could not reindex notes {}", err),
+            }
+        } else if let Err(err) = backfill_note_languages(&state.db).await {
+            log::error!("could not detect the language of notes {}", err);
+        }
+    });
+
+    // Notes that aren't edited anymore still lose revisions past retention
+    let app_handle = app.app_handle();
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        if let Err(err) = prune_note_revisions(&state.db, None).await {
+            log::error!("could not prune note revisions {}", err);
+        }
+    });
+
+    // Trashed notes past `trash_retention_days` are purged on launch
+    let app_handle = app.app_handle();
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        match purge_expired_notes(&state).await {
+            Ok(0) => {}
+            Ok(_) => {
+                let _ = app_handle.emit_all("refetch_trash", "");
+            }
+            Err(err) => log::error!("could not purge trashed notes {}", err),
+        }
     });
 
     app.run(|_, _| {});
@@ -364,3 +566,27 @@ async fn setup_word_embeddings_db(app: &App) -> Db {
 
     db
 }
+
+/// In-memory database with every migration applied
+#[cfg(test)]
+async fn setup_test_db() -> Db {
+    unsafe {
+        libsqlite3_sys::sqlite3_auto_extension(Some(std::mem::transmute(
+            sqlite3_vec_init as *const (),
+        )));
+    }
+
+    // Every connection to :memory: opens its own database, so the pool keeps
+    // exactly one open
+    let db = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+
+    sqlx::migrate!("./migrations").run(&db).await.unwrap();
+
+    db
+}
@@ -0,0 +1,77 @@
+use std::fmt::Display;
+use std::sync::mpsc;
+
+// The model, loading it first if it isn't yet. A failed load isn't kept, so
+// the next request tries again.
+fn load_once<'a, M, E: Display>(
+    model: &'a mut Option<M>,
+    name: &str,
+    load: &impl Fn() -> Result<M, E>,
+) -> anyhow::Result<&'a M> {
+    if model.is_none() {
+        match load() {
+            Ok(loaded) => *model = Some(loaded),
+            Err(err) => {
+                log::warn!("could not load {} model {}", name, err);
+                return Err(anyhow::anyhow!("{} model unavailable {}", name, err));
+            }
+        }
+    }
+
+    Ok(model.as_ref().expect("model was just loaded"))
+}
+
+/// Serve the requests sent to a model thread until every sender is dropped.
+/// The model is loaded on the first request. While it can't be loaded, e.g.
+/// offline before it was downloaded, requests are answered with the error so
+/// callers can fall back, and the next request tries to load it again.
+pub fn serve_model_requests<M, E: Display, T>(
+    receiver: mpsc::Receiver<T>,
+    name: &str,
+    load: impl Fn() -> Result<M, E>,
+    mut respond: impl FnMut(anyhow::Result<&M>, T),
+) -> anyhow::Result<()> {
+    let mut model: Option<M> = None;
+
+    // Needs to be in sync runtime, async doesn't work
+    while let Ok(request) = receiver.recv() {
+        respond(load_once(&mut model, name, &load), request);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn failed_loads_are_retried() {
+        let (sender, receiver) = mpsc::channel();
+        for request in 0..3 {
+            sender.send(request).unwrap();
+        }
+        drop(sender);
+
+        let loads = Cell::new(0);
+        let mut responses = vec![];
+
+        serve_model_requests(
+            receiver,
+            "test",
+            || {
+                loads.set(loads.get() + 1);
+                match loads.get() {
+                    1 => Err("offline"),
+                    load => Ok(load),
+                }
+            },
+            |model, request| responses.push((request, model.ok().copied())),
+        )
+        .unwrap();
+
+        assert_eq!(loads.get(), 2);
+        assert_eq!(responses, vec![(0, None), (1, Some(2)), (2, Some(2))]);
+    }
+}
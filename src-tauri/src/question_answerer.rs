@@ -0,0 +1,67 @@
+use crate::model_runner::serve_model_requests;
+use rust_bert::pipelines::question_answering::{Answer, QaInput, QuestionAnsweringModel};
+use std::fmt::Debug;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use tokio::{sync::oneshot, task};
+
+// Best answer spans kept per context
+const TOP_K: i64 = 1;
+const BATCH_SIZE: usize = 8;
+
+type Message = (
+    String,
+    Vec<String>,
+    oneshot::Sender<anyhow::Result<Vec<Vec<Answer>>>>,
+);
+
+/// Runner for extractive Question Answering
+#[derive(Debug, Clone)]
+pub struct QuestionAnswerer {
+    sender: mpsc::SyncSender<Message>,
+}
+
+impl QuestionAnswerer {
+    /// Spawn a question answerer on a separate thread and return a question
+    /// answerer instance to interact with it. The model is only loaded on the
+    /// first request.
+    pub fn spawn() -> (JoinHandle<anyhow::Result<()>>, QuestionAnswerer) {
+        let (sender, receiver) = mpsc::sync_channel(100);
+        let handle = thread::spawn(move || Self::runner(receiver));
+        (handle, QuestionAnswerer { sender })
+    }
+
+    /// The question answering runner itself
+    fn runner(receiver: mpsc::Receiver<Message>) -> anyhow::Result<()> {
+        serve_model_requests(
+            receiver,
+            "question answering",
+            || QuestionAnsweringModel::new(Default::default()),
+            |model, (question, contexts, sender)| {
+                let answers = model.map(|model| {
+                    let inputs: Vec<QaInput> = contexts
+                        .into_iter()
+                        .map(|context| QaInput {
+                            question: question.clone(),
+                            context,
+                        })
+                        .collect();
+                    model.predict(&inputs, TOP_K, BATCH_SIZE)
+                });
+                sender.send(answers).expect("sending answers");
+            },
+        )
+    }
+
+    /// Make the runner find answer spans to the question in each context. The
+    /// result holds the answers of every context, in the same order.
+    pub async fn answer(
+        &self,
+        question: String,
+        contexts: Vec<String>,
+    ) -> anyhow::Result<Vec<Vec<Answer>>> {
+        let (sender, receiver) = oneshot::channel();
+        task::block_in_place(|| self.sender.send((question, contexts, sender)))?;
+        receiver.await?
+    }
+}
@@ -1,13 +1,79 @@
 use rust_bert::pipelines::sentence_embeddings::{
     Embedding, SentenceEmbeddingsBuilder, SentenceEmbeddingsModelType,
 };
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::mpsc;
 use std::thread::{self, JoinHandle};
 use tokio::{sync::oneshot, task};
 
 type Message = (Vec<String>, oneshot::Sender<Vec<Embedding>>);
 
+/// Sentence embedding models the encoder can run. Both produce 384 dimensional
+/// embeddings so they fit the `vec_note_chunks` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SentenceEncoderBackend {
+    /// English only, downloaded by rust-bert on first use
+    AllMiniLmL12V2,
+    /// Multilingual, loaded from `<models_dir>/paraphrase-multilingual-MiniLM-L12-v2`
+    ParaphraseMultilingualMiniLmL12V2,
+}
+
+impl Default for SentenceEncoderBackend {
+    fn default() -> Self {
+        SentenceEncoderBackend::AllMiniLmL12V2
+    }
+}
+
+impl SentenceEncoderBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SentenceEncoderBackend::AllMiniLmL12V2 => "all_mini_lm_l12_v2",
+            SentenceEncoderBackend::ParaphraseMultilingualMiniLmL12V2 => {
+                "paraphrase_multilingual_mini_lm_l12_v2"
+            }
+        }
+    }
+
+    /// Directory a locally converted model is loaded from
+    fn model_dir(&self, models_dir: &Path) -> PathBuf {
+        match self {
+            SentenceEncoderBackend::AllMiniLmL12V2 => models_dir.join("all-MiniLM-L12-v2"),
+            SentenceEncoderBackend::ParaphraseMultilingualMiniLmL12V2 => {
+                models_dir.join("paraphrase-multilingual-MiniLM-L12-v2")
+            }
+        }
+    }
+
+    /// Whether the model can be loaded without a network connection
+    pub fn is_available(&self, models_dir: &Path) -> bool {
+        match self {
+            // Downloaded by rust-bert and cached after the first launch
+            SentenceEncoderBackend::AllMiniLmL12V2 => true,
+            SentenceEncoderBackend::ParaphraseMultilingualMiniLmL12V2 => {
+                self.model_dir(models_dir).join("rust_model.ot").exists()
+            }
+        }
+    }
+}
+
+impl FromStr for SentenceEncoderBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all_mini_lm_l12_v2" => Ok(SentenceEncoderBackend::AllMiniLmL12V2),
+            "paraphrase_multilingual_mini_lm_l12_v2" => {
+                Ok(SentenceEncoderBackend::ParaphraseMultilingualMiniLmL12V2)
+            }
+            _ => Err(format!("Unknown sentence encoder backend {}", s)),
+        }
+    }
+}
+
 /// Runner for Sentence Embedder
 #[derive(Debug, Clone)]
 pub struct SentenceEncoder {
@@ -17,18 +83,34 @@ pub struct SentenceEncoder {
 impl SentenceEncoder {
     /// Spawn a embedder on a separate thread and return a embedder instance
     /// to interact with it
-    pub fn spawn() -> (JoinHandle<anyhow::Result<()>>, SentenceEncoder) {
+    pub fn spawn(
+        backend: SentenceEncoderBackend,
+        models_dir: PathBuf,
+    ) -> (JoinHandle<anyhow::Result<()>>, SentenceEncoder) {
         let (sender, receiver) = mpsc::sync_channel(100);
-        let handle = thread::spawn(move || Self::runner(receiver));
+        let handle = thread::spawn(move || Self::runner(receiver, backend, models_dir));
         (handle, SentenceEncoder { sender })
     }
 
     /// The embedding runner itself
-    fn runner(receiver: mpsc::Receiver<Message>) -> anyhow::Result<()> {
+    fn runner(
+        receiver: mpsc::Receiver<Message>,
+        backend: SentenceEncoderBackend,
+        models_dir: PathBuf,
+    ) -> anyhow::Result<()> {
         // Needs to be in sync runtime, async doesn't work
-        let model = SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL12V2)
-            .create_model()
-            .unwrap();
+        let model = match backend {
+            SentenceEncoderBackend::AllMiniLmL12V2 => {
+                SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL12V2)
+                    .create_model()
+                    .unwrap()
+            }
+            SentenceEncoderBackend::ParaphraseMultilingualMiniLmL12V2 => {
+                SentenceEmbeddingsBuilder::local(backend.model_dir(&models_dir))
+                    .create_model()
+                    .unwrap()
+            }
+        };
 
         while let Ok((texts, sender)) = receiver.recv() {
             let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
@@ -0,0 +1,55 @@
+use crate::model_runner::serve_model_requests;
+use rust_bert::pipelines::summarization::SummarizationModel;
+use std::fmt::Debug;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use tokio::{sync::oneshot, task};
+
+type Message = (String, oneshot::Sender<anyhow::Result<String>>);
+
+/// Runner for Summarization
+#[derive(Debug, Clone)]
+pub struct Summarizer {
+    sender: mpsc::SyncSender<Message>,
+}
+
+impl Summarizer {
+    /// Spawn a summarizer on a separate thread and return a summarizer
+    /// instance to interact with it. The model is only loaded on the first
+    /// request.
+    pub fn spawn() -> (JoinHandle<anyhow::Result<()>>, Summarizer) {
+        let (sender, receiver) = mpsc::sync_channel(100);
+        let handle = thread::spawn(move || Self::runner(receiver));
+        (handle, Summarizer { sender })
+    }
+
+    /// The summarization runner itself
+    fn runner(receiver: mpsc::Receiver<Message>) -> anyhow::Result<()> {
+        serve_model_requests(
+            receiver,
+            "summarization",
+            || SummarizationModel::new(Default::default()),
+            |model, (text, sender)| {
+                let summary = model.and_then(|model| {
+                    model
+                        .summarize(&[text])
+                        .map_err(anyhow::Error::from)
+                        .and_then(|summaries| {
+                            summaries
+                                .into_iter()
+                                .next()
+                                .ok_or(anyhow::anyhow!("no summary generated"))
+                        })
+                });
+                sender.send(summary).expect("sending summary");
+            },
+        )
+    }
+
+    /// Make the runner summarize a text and return the summary
+    pub async fn summarize(&self, text: String) -> anyhow::Result<String> {
+        let (sender, receiver) = oneshot::channel();
+        task::block_in_place(|| self.sender.send((text, sender)))?;
+        receiver.await?
+    }
+}
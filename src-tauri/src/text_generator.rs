@@ -0,0 +1,175 @@
+use candle_core::quantized::gguf_file;
+use candle_core::{Device, Tensor};
+use candle_transformers::generation::LogitsProcessor;
+use candle_transformers::models::quantized_llama::ModelWeights;
+use std::fmt::Debug;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use tokenizers::Tokenizer;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::{sync::oneshot, task};
+
+const SEED: u64 = 299792458;
+const TEMPERATURE: f64 = 0.2;
+// End of sequence markers of the common GGUF chat models
+const STOP_TOKENS: [&str; 4] = ["</s>", "<|eot_id|>", "<|im_end|>", "<|endoftext|>"];
+
+#[derive(Debug)]
+pub struct GenerationRequest {
+    pub prompt: String,
+    /// Quantized llama-family model in the GGUF format
+    pub model_path: PathBuf,
+    /// `tokenizer.json` matching the model
+    pub tokenizer_path: PathBuf,
+    pub max_tokens: usize,
+}
+
+type Message = (
+    GenerationRequest,
+    UnboundedSender<String>,
+    oneshot::Sender<anyhow::Result<String>>,
+);
+
+struct LoadedModel {
+    model_path: PathBuf,
+    model: ModelWeights,
+    tokenizer: Tokenizer,
+    stop_tokens: Vec<u32>,
+}
+
+/// Runner for a local text generation model
+#[derive(Debug, Clone)]
+pub struct TextGenerator {
+    sender: mpsc::SyncSender<Message>,
+}
+
+impl TextGenerator {
+    /// Spawn a generator on a separate thread and return a generator instance
+    /// to interact with it. The model is only loaded on the first request.
+    pub fn spawn() -> (JoinHandle<anyhow::Result<()>>, TextGenerator) {
+        let (sender, receiver) = mpsc::sync_channel(100);
+        let handle = thread::spawn(move || Self::runner(receiver));
+        (handle, TextGenerator { sender })
+    }
+
+    fn load_model(request: &GenerationRequest) -> anyhow::Result<LoadedModel> {
+        let device = Device::Cpu;
+
+        let mut file = File::open(&request.model_path)?;
+        let content = gguf_file::Content::read(&mut file)?;
+        let model = ModelWeights::from_gguf(content, &mut file, &device)?;
+
+        let tokenizer =
+            Tokenizer::from_file(&request.tokenizer_path).map_err(anyhow::Error::msg)?;
+        let stop_tokens = STOP_TOKENS
+            .iter()
+            .filter_map(|token| tokenizer.token_to_id(token))
+            .collect();
+
+        Ok(LoadedModel {
+            model_path: request.model_path.clone(),
+            model,
+            tokenizer,
+            stop_tokens,
+        })
+    }
+
+    fn generate_tokens(
+        loaded: &mut LoadedModel,
+        request: &GenerationRequest,
+        tokens: &UnboundedSender<String>,
+    ) -> anyhow::Result<String> {
+        let device = Device::Cpu;
+
+        let prompt_tokens = loaded
+            .tokenizer
+            .encode(request.prompt.as_str(), true)
+            .map_err(anyhow::Error::msg)?
+            .get_ids()
+            .to_vec();
+
+        let mut logits_processor = LogitsProcessor::new(SEED, Some(TEMPERATURE), None);
+
+        let input = Tensor::new(prompt_tokens.as_slice(), &device)?.unsqueeze(0)?;
+        let logits = loaded.model.forward(&input, 0)?.squeeze(0)?;
+        let mut next_token = logits_processor.sample(&logits)?;
+
+        let mut generated_tokens: Vec<u32> = vec![];
+        let mut answer = String::new();
+
+        for index in 0..request.max_tokens {
+            if loaded.stop_tokens.contains(&next_token) {
+                break;
+            }
+            generated_tokens.push(next_token);
+
+            // Decode the whole answer so tokens that only form a character
+            // together are emitted once complete
+            let text = loaded
+                .tokenizer
+                .decode(&generated_tokens, true)
+                .map_err(anyhow::Error::msg)?;
+            if text.len() > answer.len() && text.starts_with(&answer) && !text.ends_with('\u{FFFD}')
+            {
+                let _ = tokens.send(text[answer.len()..].to_string());
+                answer = text;
+            }
+
+            let input = Tensor::new(&[next_token], &device)?.unsqueeze(0)?;
+            let logits = loaded
+                .model
+                .forward(&input, prompt_tokens.len() + index)?
+                .squeeze(0)?;
+            next_token = logits_processor.sample(&logits)?;
+        }
+
+        Ok(answer)
+    }
+
+    /// The generation runner itself
+    fn runner(receiver: mpsc::Receiver<Message>) -> anyhow::Result<()> {
+        let mut loaded: Option<LoadedModel> = None;
+
+        while let Ok((request, tokens, sender)) = receiver.recv() {
+            let is_loaded = loaded
+                .as_ref()
+                .is_some_and(|loaded| loaded.model_path == request.model_path);
+            if !is_loaded {
+                loaded = match Self::load_model(&request) {
+                    Ok(model) => Some(model),
+                    Err(err) => {
+                        drop(tokens);
+                        sender.send(Err(err)).expect("sending generation results");
+                        continue;
+                    }
+                };
+            }
+
+            let result = match loaded.as_mut() {
+                Some(model) => Self::generate_tokens(model, &request, &tokens),
+                None => Err(anyhow::anyhow!("model not loaded")),
+            };
+
+            // Closing the token stream before answering lets the caller stop
+            // listening for tokens
+            drop(tokens);
+            sender.send(result).expect("sending generation results");
+        }
+
+        Ok(())
+    }
+
+    /// Make the runner answer a prompt, streaming the generated text to
+    /// `tokens` as it goes, and return the full answer
+    pub async fn generate(
+        &self,
+        request: GenerationRequest,
+        tokens: UnboundedSender<String>,
+    ) -> anyhow::Result<String> {
+        let (sender, receiver) = oneshot::channel();
+        task::block_in_place(|| self.sender.send((request, tokens, sender)))?;
+        receiver.await?
+    }
+}
@@ -0,0 +1,320 @@
+/// Weighted mean of equally sized vectors. Returns None when there are no
+/// vectors, the dimensions mismatch or all weights are zero.
+pub fn weighted_centroid(vectors: &[&Vec<f32>], weights: &[f32]) -> Option<Vec<f32>> {
+    // Check if the input is empty
+    if vectors.is_empty() || vectors.len() != weights.len() {
+        return None;
+    }
+
+    let dimension = vectors[0].len();
+
+    // Check if all vectors have the same dimension
+    if !vectors.iter().all(|vector| vector.len() == dimension) {
+        return None; // Return None if dimensions mismatch
+    }
+
+    let total_weight: f32 = weights.iter().sum();
+    if total_weight <= 0.0 {
+        return None;
+    }
+
+    // Sum each dimension across all vectors, scaled by their weight
+    let mut sum_vector = vec![0.0; dimension];
+    for (vector, weight) in vectors.iter().zip(weights) {
+        for (sum, value) in sum_vector.iter_mut().zip(vector.iter()) {
+            *sum += value * weight;
+        }
+    }
+
+    // Divide by the total weight to get the centroid
+    let centroid: Vec<f32> = sum_vector.iter().map(|&sum| sum / total_weight).collect();
+
+    Some(centroid)
+}
+
+pub fn squared_euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+pub fn nearest_center(vector: &[f32], centers: &[Vec<f32>]) -> usize {
+    let mut nearest = 0;
+    let mut nearest_distance = f32::MAX;
+    for (i, center) in centers.iter().enumerate() {
+        let distance = squared_euclidean_distance(vector, center);
+        if distance < nearest_distance {
+            nearest = i;
+            nearest_distance = distance;
+        }
+    }
+    nearest
+}
+
+/// Weighted k-means. Returns up to `k` centroids along with the total weight
+/// of the vectors assigned to each of them.
+///
+/// Initialisation is deterministic (heaviest vector first, then repeatedly the
+/// vector farthest from the chosen centers) so the same input always yields
+/// the same centroids.
+pub fn kmeans(
+    vectors: &[&Vec<f32>],
+    weights: &[f32],
+    k: usize,
+    max_iterations: usize,
+) -> Vec<(Vec<f32>, f32)> {
+    if vectors.is_empty() || k == 0 || vectors.len() != weights.len() {
+        return vec![];
+    }
+
+    if vectors.len() <= k {
+        return vectors
+            .iter()
+            .zip(weights)
+            .map(|(vector, weight)| (vector.to_vec(), *weight))
+            .collect();
+    }
+
+    let heaviest = weights
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let mut centers: Vec<Vec<f32>> = vec![vectors[heaviest].to_vec()];
+
+    while centers.len() < k {
+        let farthest = vectors
+            .iter()
+            .map(|vector| {
+                centers
+                    .iter()
+                    .map(|center| squared_euclidean_distance(vector, center))
+                    .fold(f32::MAX, f32::min)
+            })
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match farthest {
+            // Every remaining vector is identical to a center
+            Some((_, distance)) if distance <= 0.0 => break,
+            Some((i, _)) => centers.push(vectors[i].to_vec()),
+            None => break,
+        }
+    }
+
+    let mut assignments: Vec<usize> = vec![usize::MAX; vectors.len()];
+
+    for _ in 0..max_iterations {
+        let new_assignments: Vec<usize> = vectors
+            .iter()
+            .map(|vector| nearest_center(vector, &centers))
+            .collect();
+
+        if new_assignments == assignments {
+            break;
+        }
+        assignments = new_assignments;
+
+        for (c, center) in centers.iter_mut().enumerate() {
+            let (members, member_weights): (Vec<&Vec<f32>>, Vec<f32>) = vectors
+                .iter()
+                .zip(weights)
+                .zip(&assignments)
+                .filter(|(_, &assignment)| assignment == c)
+                .map(|((vector, weight), _)| (*vector, *weight))
+                .unzip();
+
+            if let Some(centroid) = weighted_centroid(&members, &member_weights) {
+                *center = centroid;
+            }
+        }
+    }
+
+    centers
+        .into_iter()
+        .enumerate()
+        .filter_map(|(c, center)| {
+            let total_weight: f32 = weights
+                .iter()
+                .zip(&assignments)
+                .filter(|(_, &assignment)| assignment == c)
+                .map(|(weight, _)| weight)
+                .sum();
+
+            if total_weight > 0.0 {
+                Some((center, total_weight))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Cosine of the angle between two vectors, 0 when either is all zeros
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let norms = (dot(a, a) * dot(b, b)).sqrt();
+    if norms <= f32::EPSILON {
+        return 0.0;
+    }
+    dot(a, b) / norms
+}
+
+fn normalize(vector: &mut [f32]) -> bool {
+    let norm = dot(vector, vector).sqrt();
+    if norm <= f32::EPSILON {
+        return false;
+    }
+    vector.iter_mut().for_each(|value| *value /= norm);
+    true
+}
+
+/// Principal component analysis by power iteration with deflation. Returns
+/// the mean and up to `components` unit length principal axes, the most
+/// variance first, without ever building the covariance matrix. The largest
+/// coordinate of each axis is positive, so the same data gets the same axes.
+pub fn pca(
+    vectors: &[&Vec<f32>],
+    components: usize,
+    iterations: usize,
+) -> Option<(Vec<f32>, Vec<Vec<f32>>)> {
+    let weights = vec![1.0; vectors.len()];
+    let mean = weighted_centroid(vectors, &weights)?;
+
+    let centered: Vec<Vec<f32>> = vectors
+        .iter()
+        .map(|vector| vector.iter().zip(&mean).map(|(x, m)| x - m).collect())
+        .collect();
+
+    let mut axes: Vec<Vec<f32>> = vec![];
+
+    while axes.len() < components {
+        // Start from the centered vector with the most variance left, which
+        // can't be orthogonal to the axis we are looking for
+        let mut axis = centered
+            .iter()
+            .map(|vector| {
+                let mut residual = vector.clone();
+                for previous in &axes {
+                    let projection = dot(&residual, previous);
+                    residual
+                        .iter_mut()
+                        .zip(previous)
+                        .for_each(|(value, p)| *value -= projection * p);
+                }
+                residual
+            })
+            .max_by(|a, b| dot(a, a).total_cmp(&dot(b, b)))?;
+
+        if !normalize(&mut axis) {
+            break;
+        }
+
+        for _ in 0..iterations {
+            // axis = Xᵀ X axis, kept orthogonal to the previous axes
+            let mut next = vec![0.0; mean.len()];
+            for vector in &centered {
+                let projection = dot(vector, &axis);
+                next.iter_mut()
+                    .zip(vector)
+                    .for_each(|(value, x)| *value += projection * x);
+            }
+            for previous in &axes {
+                let projection = dot(&next, previous);
+                next.iter_mut()
+                    .zip(previous)
+                    .for_each(|(value, p)| *value -= projection * p);
+            }
+
+            if !normalize(&mut next) {
+                break;
+            }
+            axis = next;
+        }
+
+        let largest = axis
+            .iter()
+            .copied()
+            .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+            .unwrap_or(0.0);
+        if largest < 0.0 {
+            axis.iter_mut().for_each(|value| *value = -*value);
+        }
+
+        axes.push(axis);
+    }
+
+    Some((mean, axes))
+}
+
+/// Coordinates of a vector along the axes of a `pca`
+pub fn project(vector: &[f32], mean: &[f32], axes: &[Vec<f32>]) -> Vec<f32> {
+    let centered: Vec<f32> = vector.iter().zip(mean).map(|(x, m)| x - m).collect();
+
+    axes.iter().map(|axis| dot(&centered, axis)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted_centroid_skips_zero_weights() {
+        let a = vec![1.0, 0.0];
+        let b = vec![0.0, 1.0];
+
+        assert_eq!(weighted_centroid(&[&a, &b], &[0.0, 0.0]), None);
+        assert_eq!(
+            weighted_centroid(&[&a, &b], &[2.0, 0.0]),
+            Some(vec![1.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn kmeans_keeps_every_vector_when_k_is_larger() {
+        let a = vec![1.0, 0.0];
+        let b = vec![0.0, 1.0];
+
+        let clusters = kmeans(&[&a, &b], &[1.0, 2.0], 5, 10);
+        assert_eq!(clusters, vec![(a.clone(), 1.0), (b.clone(), 2.0)]);
+    }
+
+    #[test]
+    fn kmeans_of_identical_vectors_is_one_cluster() {
+        let a = vec![0.5, 0.5];
+        let vectors = vec![&a; 4];
+
+        let clusters = kmeans(&vectors, &[1.0; 4], 3, 10);
+        assert_eq!(clusters, vec![(a.clone(), 4.0)]);
+    }
+
+    #[test]
+    fn pca_axes_are_ordered_by_variance_with_a_fixed_sign() {
+        let points = [
+            vec![1.0, 10.0, 0.0],
+            vec![-1.0, 10.0, 0.0],
+            vec![1.0, -10.0, 0.0],
+            vec![-1.0, -10.0, 0.0],
+        ];
+        let vectors: Vec<&Vec<f32>> = points.iter().collect();
+
+        let (mean, axes) = pca(&vectors, 2, 50).unwrap();
+        assert_eq!(mean, vec![0.0, 0.0, 0.0]);
+        assert_eq!(axes.len(), 2);
+        assert!(cosine_similarity(&axes[0], &[0.0, 1.0, 0.0]) > 0.999);
+        assert!(cosine_similarity(&axes[1], &[1.0, 0.0, 0.0]) > 0.999);
+
+        let coordinates = project(&[2.0, -5.0, 3.0], &mean, &axes);
+        assert!((coordinates[0] + 5.0).abs() < 1e-4);
+        assert!((coordinates[1] - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cosine_similarity_of_a_zero_vector_is_zero() {
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 2.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[0.0, 0.0]), 0.0);
+        assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::model_runner::serve_model_requests;
+use rust_bert::pipelines::common::TokenizerOption;
+use rust_bert::pipelines::sequence_classification::Label;
+use rust_bert::pipelines::zero_shot_classification::{
+    ZeroShotClassificationConfig, ZeroShotClassificationOption,
+};
+use rust_bert::RustBertError;
+use rust_tokenizers::tokenizer::TruncationStrategy;
+use std::fmt::Debug;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use tch::{no_grad, Device, Kind, Tensor};
+use tokio::{sync::oneshot, task};
+
+// Tokens of each premise and hypothesis pair the model reads
+const MAX_LENGTH: usize = 256;
+// Output classes of the MNLI model
+const CONTRADICTION: i64 = 0;
+const NEUTRAL: i64 = 1;
+const ENTAILMENT: i64 = 2;
+
+#[derive(Debug)]
+pub struct ClassificationRequest {
+    pub texts: Vec<String>,
+    pub labels: Vec<String>,
+    /// Hypothesis built from each label, `{}` is replaced by the label.
+    /// Defaults to the model's "This example is about {}."
+    pub hypothesis_template: Option<String>,
+}
+
+/// How likely a premise contradicts, says nothing about or entails a
+/// hypothesis. The three add up to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Inference {
+    pub contradiction: f64,
+    pub neutral: f64,
+    pub entailment: f64,
+}
+
+enum Message {
+    Classify(
+        ClassificationRequest,
+        oneshot::Sender<anyhow::Result<Vec<Vec<Label>>>>,
+    ),
+    Infer(
+        Vec<(String, String)>,
+        oneshot::Sender<anyhow::Result<Vec<Inference>>>,
+    ),
+}
+
+/// Runner for Zero-shot classification and natural language inference
+#[derive(Debug, Clone)]
+pub struct ZeroShotClassifier {
+    sender: mpsc::SyncSender<Message>,
+}
+
+/// The MNLI model behind the zero-shot pipeline, loaded once for both jobs.
+/// The pipeline only exposes entailment against contradiction, so it is run
+/// here on its logits directly.
+struct NliModel {
+    tokenizer: TokenizerOption,
+    classifier: ZeroShotClassificationOption,
+    device: Device,
+}
+
+impl NliModel {
+    fn new() -> Result<NliModel, RustBertError> {
+        let config = ZeroShotClassificationConfig::default();
+
+        let vocab_path = config.vocab_resource.get_local_path()?;
+        let merges_path = config
+            .merges_resource
+            .as_ref()
+            .map(|resource| resource.get_local_path())
+            .transpose()?;
+        let tokenizer = TokenizerOption::from_file(
+            config.model_type,
+            vocab_path.to_str().unwrap(),
+            merges_path.as_deref().map(|path| path.to_str().unwrap()),
+            config.lower_case,
+            config.strip_accents,
+            config.add_prefix_space,
+        )?;
+        let classifier = ZeroShotClassificationOption::new(&config)?;
+
+        Ok(NliModel {
+            tokenizer,
+            classifier,
+            device: config.device,
+        })
+    }
+
+    /// Softmax over the three classes for each (premise, hypothesis) pair,
+    /// encoded as a pair the same way the zero-shot pipeline does
+    fn infer(&self, pairs: &[(&str, &str)]) -> Result<Vec<Inference>, RustBertError> {
+        if pairs.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let pad_id = self
+            .tokenizer
+            .get_pad_id()
+            .ok_or(RustBertError::ValueError(
+                "the NLI tokenizer has no padding token".into(),
+            ))?;
+
+        let tokenized = self.tokenizer.encode_pair_list(
+            pairs,
+            MAX_LENGTH,
+            &TruncationStrategy::LongestFirst,
+            0,
+        );
+        let max_len = tokenized
+            .iter()
+            .map(|input| input.token_ids.len())
+            .max()
+            .unwrap_or(0);
+        let input_ids: Vec<Tensor> = tokenized
+            .into_iter()
+            .map(|input| {
+                let mut token_ids = input.token_ids;
+                token_ids.resize(max_len, pad_id);
+                Tensor::from_slice(&token_ids)
+            })
+            .collect();
+        let input_ids = Tensor::stack(&input_ids, 0).to(self.device);
+        let mask = input_ids.ne(pad_id).to_kind(Kind::Bool);
+
+        let probabilities = no_grad(|| {
+            self.classifier
+                .forward_t(Some(&input_ids), Some(&mask), None, None, None, false)
+        })
+        .softmax(-1, Kind::Double);
+
+        Ok((0..pairs.len() as i64)
+            .map(|row| Inference {
+                contradiction: probabilities.double_value(&[row, CONTRADICTION]),
+                neutral: probabilities.double_value(&[row, NEUTRAL]),
+                entailment: probabilities.double_value(&[row, ENTAILMENT]),
+            })
+            .collect())
+    }
+
+    /// Score of every label for every text, as the zero-shot pipeline's
+    /// multilabel prediction: entailment against contradiction of the text
+    /// and the hypothesis built from the label
+    fn classify(&self, request: &ClassificationRequest) -> Result<Vec<Vec<Label>>, RustBertError> {
+        let hypotheses: Vec<String> = request
+            .labels
+            .iter()
+            .map(|label| match &request.hypothesis_template {
+                Some(template) => template.replace("{}", label),
+                None => format!("This example is about {}.", label),
+            })
+            .collect();
+
+        let pairs: Vec<(&str, &str)> = request
+            .texts
+            .iter()
+            .flat_map(|text| {
+                hypotheses
+                    .iter()
+                    .map(move |hypothesis| (text.as_str(), hypothesis.as_str()))
+            })
+            .collect();
+        let inferences = self.infer(&pairs)?;
+
+        Ok(inferences
+            .chunks(request.labels.len().max(1))
+            .enumerate()
+            .map(|(sentence, inferences)| {
+                inferences
+                    .iter()
+                    .zip(&request.labels)
+                    .enumerate()
+                    .map(|(id, (inference, label))| Label {
+                        text: label.clone(),
+                        score: inference.entailment
+                            / (inference.entailment + inference.contradiction),
+                        id: id as i64,
+                        sentence,
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+impl ZeroShotClassifier {
+    /// Spawn a classifier on a separate thread and return a classifier
+    /// instance to interact with it. The model is only loaded on the first
+    /// request.
+    pub fn spawn() -> (JoinHandle<anyhow::Result<()>>, ZeroShotClassifier) {
+        let (sender, receiver) = mpsc::sync_channel(100);
+        let handle = thread::spawn(move || Self::runner(receiver));
+        (handle, ZeroShotClassifier { sender })
+    }
+
+    /// The classification runner itself
+    fn runner(receiver: mpsc::Receiver<Message>) -> anyhow::Result<()> {
+        serve_model_requests(
+            receiver,
+            "zero-shot classification",
+            NliModel::new,
+            |model, message| match message {
+                Message::Classify(request, sender) => {
+                    let labels = model
+                        .and_then(|model| model.classify(&request).map_err(anyhow::Error::from));
+                    sender.send(labels).expect("sending labels");
+                }
+                Message::Infer(pairs, sender) => {
+                    let pairs: Vec<(&str, &str)> = pairs
+                        .iter()
+                        .map(|(premise, hypothesis)| (premise.as_str(), hypothesis.as_str()))
+                        .collect();
+                    let inferences =
+                        model.and_then(|model| model.infer(&pairs).map_err(anyhow::Error::from));
+                    sender.send(inferences).expect("sending inferences");
+                }
+            },
+        )
+    }
+
+    /// Make the runner score every label against every text independently.
+    /// The result holds the labels of each text, in the same order.
+    pub async fn classify(
+        &self,
+        request: ClassificationRequest,
+    ) -> anyhow::Result<Vec<Vec<Label>>> {
+        let (sender, receiver) = oneshot::channel();
+        task::block_in_place(|| self.sender.send(Message::Classify(request, sender)))?;
+        receiver.await?
+    }
+
+    /// Make the runner tell for each (premise, hypothesis) pair whether the
+    /// premise contradicts, is neutral to or entails the hypothesis
+    pub async fn infer(&self, pairs: Vec<(String, String)>) -> anyhow::Result<Vec<Inference>> {
+        let (sender, receiver) = oneshot::channel();
+        task::block_in_place(|| self.sender.send(Message::Infer(pairs, sender)))?;
+        receiver.await?
+    }
+}
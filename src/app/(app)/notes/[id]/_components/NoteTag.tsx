@@ -1,16 +1,17 @@
 import { selectedTagsAtom } from "@/app/_state";
 import { Badge } from "@/components/ui/badge";
 import { useNoteTagDelete } from "@/hooks/mutations/notes/use-note-tag-delete";
+import { Tag } from "@/types";
 import { useAtom } from "jotai";
 import { Search, X } from "lucide-react";
 import { useRouter } from "next/navigation";
 
 type NoteTagProps = {
   noteId: number;
-  tagId: string;
+  tag: Tag;
 };
 
-export function NoteTag({ noteId, tagId }: NoteTagProps) {
+export function NoteTag({ noteId, tag }: NoteTagProps) {
   const router = useRouter();
   const [, setSelectedTags] = useAtom(selectedTagsAtom);
 
@@ -23,18 +24,18 @@ export function NoteTag({ noteId, tagId }: NoteTagProps) {
     >
       <div
         onClick={() => {
-          setSelectedTags([tagId]);
+          setSelectedTags([tag.id]);
           router.push(`/notes`);
         }}
         className="gap-1 flex items-center p-1 hover:bg-muted"
       >
         <Search className="invisible w-3 h-3 group-hover:visible" />
-        {tagId}
+        {tag.path}
       </div>
       <div className="p-1 h-full flex items-center hover:bg-muted invisible group-hover:visible">
         <X
           onClick={async () => {
-            await deleteNoteTag({ tagId });
+            await deleteNoteTag({ tagId: tag.id });
           }}
           className="w-3 h-3"
         />
@@ -11,7 +11,7 @@ export function RelatedWords({ noteId }: { noteId: number }) {
 
   const { data: note } = useNote({ id: noteId });
 
-  const wordForTagsInNote = note?.tags.map((tag) => tag.id);
+  const wordForTagsInNote = note?.tags.map((tag) => tag.name);
 
   return (
     <div className="flex flex-col flex-grow">
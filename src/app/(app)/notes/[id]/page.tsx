@@ -62,6 +62,7 @@ export default function Page({ params }: { params: { id: string } }) {
     invoke("get_note", { id }).then((note) => {
       setNoteContent((note as Note).content);
     });
+    invoke("record_note_view", { noteId: id });
     setTouched(false);
   }, []);
 
@@ -90,7 +91,7 @@ export default function Page({ params }: { params: { id: string } }) {
           <div className="flex gap-1 items-center">
             <div className="flex gap-1">
               {note?.tags.map((tag) => (
-                <NoteTag key={tag.id} tagId={tag.id} noteId={id} />
+                <NoteTag key={tag.id} tag={tag} noteId={id} />
               ))}
             </div>
 
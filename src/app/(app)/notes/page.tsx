@@ -6,34 +6,35 @@ import { Badge } from "@/components/ui/badge";
 import { ScrollArea } from "@/components/ui/scroll-area";
 import { useNotes } from "@/hooks/queries/notes/use-notes";
 import { useTags } from "@/hooks/queries/tags/use-tags";
+import { TagDetails } from "@/types";
 import { useAtom } from "jotai";
 
 type TagBadgeProps = {
-  tagId: string;
+  tag: TagDetails;
 };
-function TagBadge({ tagId }: TagBadgeProps) {
+function TagBadge({ tag }: TagBadgeProps) {
   const [selectedTags, setSelectedTags] = useAtom(selectedTagsAtom);
 
-  const isSelected = selectedTags.includes(tagId);
+  const isSelected = selectedTags.includes(tag.id);
 
   return (
     <Badge
       onClick={() => {
         isSelected
-          ? setSelectedTags(selectedTags.filter((tag) => tag !== tagId))
-          : setSelectedTags([...new Set(selectedTags), tagId]);
+          ? setSelectedTags(selectedTags.filter((id) => id !== tag.id))
+          : setSelectedTags([...new Set(selectedTags), tag.id]);
       }}
       variant={isSelected ? "default" : "secondary"}
       className="cursor-pointer"
     >
-      {tagId}
+      {tag.path} ({tag.total_note_count})
     </Badge>
   );
 }
 
 export default function Page() {
   const [selectedTags, setSelectedTags] = useAtom(selectedTagsAtom);
-  const { data: tags } = useTags();
+  const { data: tags } = useTags({ tagIds: selectedTags });
 
   const { data: notes } = useNotes({
     tag_ids: selectedTags,
@@ -43,7 +44,7 @@ export default function Page() {
     <div className="flex flex-col gap-2 flex-grow  overflow-auto">
       <div className="flex gap-2 flex-wrap p-2">
         {tags?.map((tag) => (
-          <TagBadge key={tag.id} tagId={tag.id} />
+          <TagBadge key={tag.id} tag={tag} />
         ))}
       </div>
       <ScrollArea>
@@ -1,3 +1,3 @@
 import { atom } from "jotai";
 
-export const selectedTagsAtom = atom<string[]>([]);
+export const selectedTagsAtom = atom<number[]>([]);
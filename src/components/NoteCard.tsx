@@ -30,8 +30,8 @@ export function NoteCard({ note, variant = "sm", className }: NoteCardProps) {
       ) : (
         <span className="text-sm overflow-hidden text-ellipsis whitespace-pre-wrap">
           {variant === "sm"
-            ? extractFirstLineOfString(note.content).slice(0, 100)
-            : note.content.slice(0, 200)}
+            ? (note.title ?? extractFirstLineOfString(note.content)).slice(0, 100)
+            : (note.summary ?? note.content).slice(0, 200)}
         </span>
       )}
       <span className="text-xs text-gray-600 text-end">
@@ -1,15 +1,16 @@
 import { selectedTagsAtom } from "@/app/_state";
 import { Badge } from "@/components/ui/badge";
 import { useTagDelete } from "@/hooks/mutations/tags/use-tag-delete";
+import { Tag } from "@/types";
 import { useAtom } from "jotai";
 import { Search, X } from "lucide-react";
 import { useRouter } from "next/navigation";
 
 type NoteTagProps = {
-  tagId: string;
+  tag: Tag;
 };
 
-export function NoteTag({ tagId }: NoteTagProps) {
+export function NoteTag({ tag }: NoteTagProps) {
   const router = useRouter();
   const [selectedTags, setSelectedTags] = useAtom(selectedTagsAtom);
 
@@ -22,18 +23,18 @@ export function NoteTag({ tagId }: NoteTagProps) {
     >
       <div
         onClick={() => {
-          setSelectedTags([tagId]);
+          setSelectedTags([tag.id]);
           router.push(`/notes`);
         }}
         className="gap-1 flex items-center p-1 hover:bg-muted"
       >
         <Search className="invisible w-3 h-3 group-hover:visible" />
-        {tagId}
+        {tag.path}
       </div>
       <div className="p-1 h-full flex items-center hover:bg-muted invisible group-hover:visible">
         <X
           onClick={async () => {
-            await deleteTag({ id: tagId });
+            await deleteTag({ id: tag.id });
           }}
           className="w-3 h-3"
         />
@@ -74,7 +74,7 @@ export function Sidebar() {
         <ScrollArea>
           <div className="flex gap-1 p-1 flex-wrap">
             {tags?.map((tag) => (
-              <NoteTag key={tag.id} tagId={tag.id} />
+              <NoteTag key={tag.id} tag={tag} />
             ))}
           </div>
         </ScrollArea>
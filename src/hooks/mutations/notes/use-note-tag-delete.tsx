@@ -3,7 +3,7 @@
 import { useMutation, useQueryClient } from "@tanstack/react-query";
 import { invoke } from "@tauri-apps/api/tauri";
 
-type DeleteNoteTagParams = { tagId: string; noteId: number };
+type DeleteNoteTagParams = { tagId: number; noteId: number };
 
 async function deleteNoteTag({ tagId, noteId }: DeleteNoteTagParams) {
   const res = await invoke("delete_note_tag", {
@@ -18,7 +18,7 @@ export const useNoteTagDelete = ({ noteId }: { noteId: number }) => {
   const queryClient = useQueryClient();
 
   const mutation = useMutation({
-    mutationFn: ({ tagId }: { tagId: string }) =>
+    mutationFn: ({ tagId }: { tagId: number }) =>
       deleteNoteTag({ noteId, tagId }),
     onSuccess: () => {
       queryClient.invalidateQueries({ queryKey: ["notes"] });
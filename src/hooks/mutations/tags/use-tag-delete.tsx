@@ -3,7 +3,7 @@
 import { useMutation, useQueryClient } from "@tanstack/react-query";
 import { invoke } from "@tauri-apps/api/tauri";
 
-type DeleteTagParams = { id: string };
+type DeleteTagParams = { id: number };
 
 async function deleteTag({ id }: DeleteTagParams) {
   const res = await invoke("delete_tag", {
@@ -17,7 +17,7 @@ export const useTagDelete = () => {
   const queryClient = useQueryClient();
 
   const mutation = useMutation({
-    mutationFn: ({ id }: { id: string }) => deleteTag({ id }),
+    mutationFn: ({ id }: { id: number }) => deleteTag({ id }),
     onSuccess: () => {
       queryClient.invalidateQueries({ queryKey: ["notes"] });
       queryClient.invalidateQueries({ queryKey: ["tags"] });
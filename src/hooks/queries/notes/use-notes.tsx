@@ -5,10 +5,14 @@ import { useQuery } from "@tanstack/react-query";
 import { invoke } from "@tauri-apps/api/tauri";
 
 type SearchParams = {
-  tag_ids?: string[];
+  tag_ids?: number[];
   match_all?: boolean;
   skip?: number;
   take?: number;
+  pinned?: boolean;
+  archived?: boolean;
+  favourite?: boolean;
+  notebook_id?: number;
 };
 
 export const useNotes = ({
@@ -16,9 +20,23 @@ export const useNotes = ({
   match_all = false,
   skip = 0,
   take = 50,
+  pinned,
+  archived,
+  favourite,
+  notebook_id,
 }: SearchParams) => {
   const query = useQuery({
-    queryKey: ["notes", tag_ids, match_all, skip, take],
+    queryKey: [
+      "notes",
+      tag_ids,
+      match_all,
+      skip,
+      take,
+      pinned,
+      archived,
+      favourite,
+      notebook_id,
+    ],
     queryFn: async () => {
       const notes = await invoke("get_notes", {
         params: {
@@ -26,6 +44,10 @@ export const useNotes = ({
           match_all,
           skip,
           take,
+          pinned,
+          archived,
+          favourite,
+          notebook_id,
         },
       });
       return notes as Note[];
@@ -1,15 +1,26 @@
 "use client";
 
-import { Tag } from "@/types";
+import { TagDetails, TagSort } from "@/types";
 import { useQuery } from "@tanstack/react-query";
 import { invoke } from "@tauri-apps/api/tauri";
 
-export const useTags = () => {
+type TagsParams = {
+  sort?: TagSort;
+  // only tags on notes matching these tags, for narrowing down
+  tagIds?: number[];
+  matchAll?: boolean;
+};
+
+export const useTags = ({
+  sort = "name",
+  tagIds = [],
+  matchAll = false,
+}: TagsParams = {}) => {
   const query = useQuery({
-    queryKey: ["tags"],
+    queryKey: ["tags", sort, tagIds, matchAll],
     queryFn: async () => {
-      const tags = await invoke("get_tags");
-      return tags as Tag[];
+      const tags = await invoke("get_tags", { sort, tagIds, matchAll });
+      return tags as TagDetails[];
     },
   });
 
@@ -1,6 +1,17 @@
 export type Note = {
   id: number;
   content: string;
+  // generated from the summary or the first heading, unless set by the user
+  title: string | null;
+  summary: string | null;
+  title_is_custom: boolean;
+  // ISO 639-3 code detected from the content, e.g. "eng", "jpn", "deu"
+  language: string | null;
+  pinned: boolean;
+  // left out of the notes list unless asked for, still searchable
+  archived: boolean;
+  favourite: boolean;
+  notebook_id: number;
   // seconds since Unix epoch.
   // for js, it needs to be milliseconds
   created_at: number;
@@ -9,5 +20,23 @@ export type Note = {
 };
 
 export type Tag = {
-  id: string;
+  id: number;
+  name: string;
+  parent_id: number | null;
+  // names from the top level tag down, e.g. "work/infra/db"
+  path: string;
 };
+
+export type TagDetails = Tag & {
+  colour: string | null;
+  icon: string | null;
+  description: string | null;
+  created_at: number;
+  // notes with the tag itself
+  note_count: number;
+  // notes with the tag or any tag below it
+  total_note_count: number;
+  last_used_at: number | null;
+};
+
+export type TagSort = "name" | "count" | "recent";